@@ -37,34 +37,6 @@ L16:
         jump L0A
 "#;
 
-#[derive(Debug)]
-struct Game;
-
-impl bytecode::Game for Game {
-    fn is_second_round(&self) -> bool {
-        false
-    }
-    fn stage(&self) -> u8 {
-        1
-    }
-
-    fn hero_x(&self) -> u8 {
-        128
-    }
-    fn hero_y(&self) -> u8 {
-        120
-    }
-
-    fn rand(&mut self) -> u8 {
-        0
-    }
-
-    fn try_shoot_aim(&mut self, _x: u8, _y: u8, _speed_mask: u8, _force_homing: bool) {}
-
-    fn restore_music(&mut self) {}
-    fn play_sound(&mut self, _sound: u8) {}
-}
-
 fn window_conf() -> Conf {
     Conf {
         window_title: "interpret".to_owned(),
@@ -76,7 +48,18 @@ fn window_conf() -> Conf {
 
 #[macroquad::main(window_conf)]
 async fn main() -> eyre::Result<()> {
-    let mut game = Game;
+    let mut game = bytecode::GameParts {
+        env: bytecode::FixedEnvironment {
+            stage: 1,
+            second_round: false,
+            hero_x: 128,
+            hero_y: 120,
+            rank: None,
+        },
+        rng: bytecode::SeededRng::default(),
+        shooter: (),
+        audio: (),
+    };
 
     let mut interp = bytecode::InterpreterInit {
         program: bytecode::asm(ASM.as_bytes())?,
@@ -93,8 +76,11 @@ async fn main() -> eyre::Result<()> {
 
         x: 120,
         y: 239,
+
+        op_budget: None,
+        clip: bytecode::ClipConfig::ROM,
     }
-    .init();
+    .init()?;
 
     set_camera(&Camera2D::from_display_rect(Rect::new(
         0.0,
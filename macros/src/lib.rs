@@ -0,0 +1,41 @@
+//! コンパイル時にバイトコードをアセンブルするマクロ。
+
+use proc_macro::TokenStream;
+use quote::quote;
+
+/// アセンブリ文字列リテラルをコンパイル時にアセンブルし、`[u8; N]` 式に展開する。
+///
+/// アセンブルエラーはマクロ入力を指すコンパイルエラーとして報告される。
+/// エラーの位置は文字列リテラル全体を指す (リテラル内の位置を指す API が stable にないため)。
+/// エラーメッセージに含まれる行番号はリテラル内の行番号である。
+///
+/// ```
+/// use starsoldier_bytecode_macros::bytecode;
+///
+/// const PROGRAM: &[u8] = &bytecode!(
+///     r#"
+/// L00:
+///         move 0x26
+///         jump L00
+/// "#
+/// );
+///
+/// let expected = starsoldier_bytecode::asm("L00:\nmove 0x26\njump L00\n".as_bytes()).unwrap();
+/// assert_eq!(PROGRAM, expected.as_slice());
+/// ```
+///
+/// ```compile_fail
+/// use starsoldier_bytecode_macros::bytecode;
+///
+/// // 未定義のラベル
+/// const PROGRAM: &[u8] = &bytecode!("jump L00");
+/// ```
+#[proc_macro]
+pub fn bytecode(input: TokenStream) -> TokenStream {
+    let lit = syn::parse_macro_input!(input as syn::LitStr);
+
+    match starsoldier_bytecode::asm(lit.value().as_bytes()) {
+        Ok(buf) => quote!([#(#buf),*]).into(),
+        Err(e) => syn::Error::new(lit.span(), e).to_compile_error().into(),
+    }
+}
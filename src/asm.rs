@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use logos::{Lexer, Logos};
 use thiserror::Error;
@@ -17,6 +17,18 @@ pub enum AsmError {
     #[error("line {lineno}: undefined label: {label}")]
     UndefinedLabel { lineno: usize, label: String },
 
+    #[error("line {lineno}: duplicate label: {label}")]
+    DuplicateLabel { lineno: usize, label: String },
+
+    #[error("line {lineno}: {operand} must be {min}..={max}, got {value}")]
+    OutOfRange {
+        lineno: usize,
+        operand: String,
+        min: i64,
+        max: i64,
+        value: i64,
+    },
+
     #[error("line {lineno}: set_jump_on_damage 0 is not permitted")]
     SetJumpOnDamageZero { lineno: usize },
 
@@ -107,14 +119,36 @@ enum Token {
     #[regex(r"[A-Za-z_][[:word:]]*:", |lex| lex.slice()[0..lex.slice().len()-1].to_owned())]
     LabelDefinition(String),
 
-    #[regex(r"[A-Za-z_][[:word:]]*", |lex| lex.slice().to_owned())]
+    // "Main.loop" のようにスコープで修飾すれば、他のスコープのローカルラベルも参照できる。
+    #[regex(r"[A-Za-z_][[:word:]]*(\.[A-Za-z_][[:word:]]*)?", |lex| lex.slice().to_owned())]
     LabelReference(String),
 
-    #[regex(r"0x[A-Fa-f0-9]+", |lex| u8::from_str_radix(&lex.slice()[2..], 16))]
-    #[regex(r"0o[0-7]+", |lex| u8::from_str_radix(&lex.slice()[2..], 8))]
-    #[regex(r"0b[01]+", |lex| u8::from_str_radix(&lex.slice()[2..], 2))]
-    #[regex(r"[0-9]+", |lex| u8::from_str_radix(lex.slice(), 10))]
-    Number(u8),
+    // ローカルラベル。直前のグローバルラベルをスコープとする。
+    #[regex(r"\.[A-Za-z_][[:word:]]*:", |lex| lex.slice()[0..lex.slice().len()-1].to_owned())]
+    LocalLabelDefinition(String),
+
+    #[regex(r"\.[A-Za-z_][[:word:]]*", |lex| lex.slice().to_owned())]
+    LocalLabelReference(String),
+
+    // 無名ラベル (ca65 形式)。":" で定義し、":+", ":--" などで前方/後方を参照する。
+    #[regex(r":")]
+    AnonymousLabelDefinition,
+
+    #[regex(r":\++", |lex| lex.slice().len() - 1)]
+    AnonymousLabelForward(usize),
+
+    #[regex(r":-+", |lex| lex.slice().len() - 1)]
+    AnonymousLabelBackward(usize),
+
+    // 値の範囲チェックはオペランドごとに行う。i64 に収まらない数値は None。
+    #[regex(r"-?0x[A-Fa-f0-9]+", |lex| Some(parse_number(lex.slice(), "0x", 16)))]
+    #[regex(r"-?\$[A-Fa-f0-9]+", |lex| Some(parse_number(lex.slice(), "$", 16)))]
+    #[regex(r"-?0o[0-7]+", |lex| Some(parse_number(lex.slice(), "0o", 8)))]
+    #[regex(r"-?0b[01]+", |lex| Some(parse_number(lex.slice(), "0b", 2)))]
+    #[regex(r"-?%[01]+", |lex| Some(parse_number(lex.slice(), "%", 2)))]
+    #[regex(r"-?[0-9]+", |lex| Some(parse_number(lex.slice(), "", 10)))]
+    #[regex(r"'[ -&(-\[\]-~]'", |lex| Some(Some(i64::from(lex.slice().as_bytes()[1]))))]
+    Number(Option<i64>),
 
     #[regex(r",")]
     Comma,
@@ -124,41 +158,111 @@ enum Token {
     Error,
 }
 
+#[derive(Debug)]
+enum LabelRef {
+    Named(String),
+
+    // 無名ラベルの定義順インデックス。範囲外なら未定義。
+    // name はエラーメッセージ用の元の表記。
+    Anonymous { index: isize, name: String },
+}
+
+impl LabelRef {
+    fn into_name(self) -> String {
+        match self {
+            Self::Named(label) => label,
+            Self::Anonymous { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Labels {
+    named: HashMap<String, u8>,
+    anonymous: Vec<u8>,
+    scope: Option<String>,
+}
+
+impl Labels {
+    fn define(&mut self, lineno: usize, label: String, addr: u8) -> AsmResult<()> {
+        if self.named.contains_key(&label) {
+            return Err(AsmError::DuplicateLabel { lineno, label });
+        }
+        self.named.insert(label, addr);
+        Ok(())
+    }
+
+    /// ローカルラベル名を現在のスコープで修飾する。
+    fn qualify_local(&self, lineno: usize, local: &str) -> AsmResult<String> {
+        let scope = self.scope.as_ref().ok_or_else(|| AsmError::Parse {
+            lineno,
+            msg: format!("local label without preceding global label: {}", local),
+        })?;
+        Ok(format!("{}{}", scope, local))
+    }
+
+    fn resolve(&self, label: &LabelRef) -> Option<u8> {
+        match label {
+            LabelRef::Named(label) => self.named.get(label).copied(),
+            LabelRef::Anonymous { index, .. } => {
+                use std::convert::TryFrom;
+                usize::try_from(*index)
+                    .ok()
+                    .and_then(|index| self.anonymous.get(index).copied())
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Statement {
     lineno: usize,
-    addr: usize,
     op: Op,
-    label: Option<String>,
+    label: Option<LabelRef>,
 }
 
 impl Statement {
-    fn new(lineno: usize, addr: usize, op: Op) -> Self {
+    fn new(lineno: usize, op: Op) -> Self {
         Self {
             lineno,
-            addr,
             op,
             label: None,
         }
     }
 
-    fn with_label(lineno: usize, addr: usize, op: Op, label: String) -> Self {
+    fn with_label(lineno: usize, op: Op, label: LabelRef) -> Self {
         Self {
             lineno,
-            addr,
             op,
             label: Some(label),
         }
     }
 }
 
+/// アセンブル結果とデバッグ情報。
+#[derive(Debug)]
+pub struct Assembly {
+    pub code: Vec<u8>,
+
+    /// 命令のアドレスから、それを定義したソースの行番号への対応。
+    pub addr_to_lineno: BTreeMap<usize, usize>,
+
+    /// ラベル名からアドレスへの対応。ローカルラベルはスコープで修飾した名前 ("Main.loop") になる。
+    pub labels: HashMap<String, u8>,
+}
+
 pub fn asm<R: std::io::Read>(rdr: R) -> AsmResult<Vec<u8>> {
+    assemble(rdr).map(|assembly| assembly.code)
+}
+
+/// asm() と同様だが、デバッグ情報も返す。
+pub fn assemble<R: std::io::Read>(rdr: R) -> AsmResult<Assembly> {
     use std::io::BufRead as _;
 
     let rdr = std::io::BufReader::new(rdr);
 
     let mut stmts = vec![];
-    let mut label_to_addr = HashMap::new();
+    let mut labels = Labels::default();
 
     let mut addr = 0;
     for (i, line) in rdr.lines().enumerate() {
@@ -169,36 +273,45 @@ pub fn asm<R: std::io::Read>(rdr: R) -> AsmResult<Vec<u8>> {
             continue;
         }
 
-        parse_line(lineno, line, &mut addr, &mut stmts, &mut label_to_addr)?;
+        parse_line(lineno, line, &mut addr, &mut stmts, &mut labels)?;
         if addr > 0x100 {
             return Err(AsmError::Overflow { lineno });
         }
     }
 
-    resolve_labels(&mut stmts, &label_to_addr)?;
+    resolve_labels(&mut stmts, &labels)?;
 
     let mut buf = vec![0_u8; addr];
-    emit_code(&mut buf, &stmts);
+    let addr_to_lineno = emit_code(&mut buf, &stmts);
 
-    Ok(buf)
+    Ok(Assembly {
+        code: buf,
+        addr_to_lineno,
+        labels: labels.named,
+    })
 }
 
-fn emit_code(buf: &mut [u8], stmts: &[Statement]) {
+fn emit_code(buf: &mut [u8], stmts: &[Statement]) -> BTreeMap<usize, usize> {
+    let mut addr_to_lineno = BTreeMap::new();
+
     let mut addr = 0;
-    for op in stmts.iter().map(|stmt| stmt.op) {
-        op.encode(&mut buf[addr..]);
-        addr += op.len();
+    for stmt in stmts {
+        stmt.op.encode(&mut buf[addr..]);
+        addr_to_lineno.insert(addr, stmt.lineno);
+        addr += stmt.op.len();
     }
+
+    addr_to_lineno
 }
 
-fn resolve_labels(stmts: &mut [Statement], label_to_addr: &HashMap<String, u8>) -> AsmResult<()> {
+fn resolve_labels(stmts: &mut [Statement], labels: &Labels) -> AsmResult<()> {
     for stmt in stmts {
         if let Some(label) = stmt.label.take() {
-            let addr = *label_to_addr
-                .get(&label)
+            let addr = labels
+                .resolve(&label)
                 .ok_or_else(|| AsmError::UndefinedLabel {
                     lineno: stmt.lineno,
-                    label,
+                    label: label.into_name(),
                 })?;
             stmt.op = match stmt.op {
                 Op::Jump(_) => Op::Jump(addr),
@@ -227,7 +340,7 @@ fn parse_line(
     line: &str,
     addr: &mut usize,
     stmts: &mut Vec<Statement>,
-    labels: &mut HashMap<String, u8>,
+    labels: &mut Labels,
 ) -> AsmResult<()> {
     use std::convert::TryFrom;
 
@@ -237,7 +350,7 @@ fn parse_line(
     macro_rules! add_stmt {
         ($op:expr) => {{
             let op = $op;
-            stmts.push(Statement::new(lineno, *addr, op));
+            stmts.push(Statement::new(lineno, op));
             *addr += op.len();
         }};
     }
@@ -245,7 +358,7 @@ fn parse_line(
     macro_rules! add_stmt_with_label {
         ($op:expr, $label:expr) => {{
             let op = $op;
-            stmts.push(Statement::with_label(lineno, *addr, op, $label));
+            stmts.push(Statement::with_label(lineno, op, $label));
             *addr += op.len();
         }};
     }
@@ -253,29 +366,41 @@ fn parse_line(
     match lex.next() {
         Some(Token::LabelDefinition(label)) => {
             expect_end(lineno, lex)?;
-            labels.insert(label, u8::try_from(*addr).unwrap());
+            labels.define(lineno, label.clone(), u8::try_from(*addr).unwrap())?;
+            labels.scope = Some(label);
+        }
+
+        Some(Token::LocalLabelDefinition(local)) => {
+            expect_end(lineno, lex)?;
+            let label = labels.qualify_local(lineno, &local)?;
+            labels.define(lineno, label, u8::try_from(*addr).unwrap())?;
+        }
+
+        Some(Token::AnonymousLabelDefinition) => {
+            expect_end(lineno, lex)?;
+            labels.anonymous.push(u8::try_from(*addr).unwrap());
         }
 
         Some(Token::MnemonicMove) => {
-            let dir = expect_dir(lineno, lex)?;
+            let dir = expect_dir(lineno, lex, "move direction")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_move(dir));
         }
 
         Some(Token::MnemonicJump) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_jump(0), label);
         }
 
         Some(Token::MnemonicSetSleepTimer) => {
-            let idx = expect_nibble(lineno, lex)?;
+            let idx = expect_nibble(lineno, lex, "set_sleep_timer index")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_sleep_timer(idx));
         }
 
         Some(Token::MnemonicLoopBegin) => {
-            let idx = expect_loop_idx(lineno, lex)?;
+            let idx = expect_loop_idx(lineno, lex, "loop_begin count")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_loop_begin(idx));
         }
@@ -286,41 +411,41 @@ fn parse_line(
         }
 
         Some(Token::MnemonicShootDirection) => {
-            let dir = expect_dir_shoot(lineno, lex)?;
+            let dir = expect_dir_shoot(lineno, lex, "shoot_direction direction")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_shoot_direction(dir));
         }
 
         Some(Token::MnemonicSetSprite) => {
-            let idx = expect_nibble(lineno, lex)?;
+            let idx = expect_nibble(lineno, lex, "set_sprite index")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_sprite(idx));
         }
 
         Some(Token::MnemonicSetHomingTimer) => {
-            let idx = expect_nibble(lineno, lex)?;
+            let idx = expect_nibble(lineno, lex, "set_homing_timer index")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_homing_timer(idx));
         }
 
         Some(Token::MnemonicSetInversion) => {
-            let inv_x = expect_bool(lineno, lex)?;
+            let inv_x = expect_bool(lineno, lex, "set_inversion x")?;
             expect_comma(lineno, lex)?;
-            let inv_y = expect_bool(lineno, lex)?;
+            let inv_y = expect_bool(lineno, lex, "set_inversion y")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_inversion(inv_x, inv_y));
         }
 
         Some(Token::MnemonicSetPosition) => {
-            let x = expect_number(lineno, lex)?;
+            let x = expect_byte(lineno, lex, "set_position x", 0..=0xFF)?;
             expect_comma(lineno, lex)?;
-            let y = expect_number(lineno, lex)?;
+            let y = expect_byte(lineno, lex, "set_position y", 0..=0xFF)?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_position(x, y));
         }
 
         Some(Token::MnemonicSetJumpOnDamage) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_set_jump_on_damage(0xFF), label);
         }
@@ -331,7 +456,7 @@ fn parse_line(
         }
 
         Some(Token::MnemonicSetHealth) => {
-            let health = expect_number(lineno, lex)?;
+            let health = expect_byte(lineno, lex, "set_health health", 0..=0xFF)?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_jump_on_damage(health));
         }
@@ -347,49 +472,49 @@ fn parse_line(
         }
 
         Some(Token::MnemonicSetPart) => {
-            let part = expect_number(lineno, lex)?;
+            let part = expect_byte(lineno, lex, "set_part part", 0..=0xFF)?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_set_part(part));
         }
 
         Some(Token::MnemonicRandomizeX) => {
-            let mask = expect_number(lineno, lex)?;
+            let mask = expect_mask(lineno, lex, "randomize_x mask")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_randomize_x(mask));
         }
 
         Some(Token::MnemonicRandomizeY) => {
-            let mask = expect_number(lineno, lex)?;
+            let mask = expect_mask(lineno, lex, "randomize_y mask")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_randomize_y(mask));
         }
 
         Some(Token::MnemonicBccX) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_bcc_x(0), label);
         }
 
         Some(Token::MnemonicBcsX) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_bcs_x(0), label);
         }
 
         Some(Token::MnemonicBccY) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_bcc_y(0), label);
         }
 
         Some(Token::MnemonicBcsY) => {
-            let label = expect_label_reference(lineno, lex)?;
+            let label = expect_label_reference(lineno, lex, labels)?;
             expect_end(lineno, lex)?;
             add_stmt_with_label!(Op::new_bcs_y(0), label);
         }
 
         Some(Token::MnemonicShootAim) => {
-            let unused = expect_nibble(lineno, lex)?;
+            let unused = expect_nibble(lineno, lex, "shoot_aim operand")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_shoot_aim(unused));
         }
@@ -400,7 +525,7 @@ fn parse_line(
         }
 
         Some(Token::MnemonicPlaySound) => {
-            let sound = expect_sound(lineno, lex)?;
+            let sound = expect_sound(lineno, lex, "play_sound sound")?;
             expect_end(lineno, lex)?;
             add_stmt!(Op::new_play_sound(sound));
         }
@@ -416,107 +541,123 @@ fn parse_line(
     Ok(())
 }
 
-fn expect_label_reference(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<String> {
-    if let Some(Token::LabelReference(label)) = lex.next() {
-        Ok(label)
-    } else {
-        Err(AsmError::Parse {
+fn expect_label_reference(
+    lineno: usize,
+    lex: &mut Lexer<Token>,
+    labels: &Labels,
+) -> AsmResult<LabelRef> {
+    // 無名ラベル参照は、その時点で定義済みの無名ラベル数を基準に解決する。
+    let n_anonymous = labels.anonymous.len() as isize;
+
+    match lex.next() {
+        Some(Token::LabelReference(label)) => Ok(LabelRef::Named(label)),
+        Some(Token::LocalLabelReference(local)) => {
+            Ok(LabelRef::Named(labels.qualify_local(lineno, &local)?))
+        }
+        Some(Token::AnonymousLabelForward(n)) => Ok(LabelRef::Anonymous {
+            index: n_anonymous + n as isize - 1,
+            name: lex.slice().to_owned(),
+        }),
+        Some(Token::AnonymousLabelBackward(n)) => Ok(LabelRef::Anonymous {
+            index: n_anonymous - n as isize,
+            name: lex.slice().to_owned(),
+        }),
+        _ => Err(AsmError::Parse {
             lineno,
             msg: format!("expected label reference, but got: {}", lex.slice()),
-        })
+        }),
     }
 }
 
-fn expect_dir(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<Direction> {
-    let idx = expect_number(lineno, lex)?;
-
-    if !(0..=0x3F).contains(&idx) {
-        return Err(AsmError::Parse {
-            lineno,
-            msg: format!("invalid direction: {}", idx),
-        });
-    }
-
+fn expect_dir(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<Direction> {
+    let idx = expect_byte(lineno, lex, operand, 0..=0x3F)?;
     Ok(Direction::new(idx))
 }
 
-fn expect_dir_shoot(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<Direction> {
-    let idx = expect_number(lineno, lex)?;
-
-    if !(0..=0xF).contains(&idx) {
-        return Err(AsmError::Parse {
-            lineno,
-            msg: format!("invalid shooting direction: {}", idx),
-        });
-    }
-
+fn expect_dir_shoot(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<Direction> {
+    let idx = expect_byte(lineno, lex, operand, 0..=0xF)?;
     Ok(Direction::new(idx))
 }
 
-fn expect_nibble(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<u8> {
-    const RANGE: std::ops::RangeInclusive<u8> = 0..=0xF;
+fn expect_nibble(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<u8> {
+    expect_byte(lineno, lex, operand, 0..=0xF)
+}
 
-    let idx = expect_number(lineno, lex)?;
+fn expect_loop_idx(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<u8> {
+    let idx = expect_byte(lineno, lex, operand, 0..=0xF)?;
 
-    if !RANGE.contains(&idx) {
+    if idx == 1 {
         return Err(AsmError::Parse {
             lineno,
-            msg: format!("operand must be within {:?}: {}", RANGE, idx),
+            msg: format!("{} must not be 1", operand),
         });
     }
 
     Ok(idx)
 }
 
-fn expect_loop_idx(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<u8> {
-    let idx = expect_number(lineno, lex)?;
-
-    if !(0..=0xF).contains(&idx) || idx == 1 {
-        return Err(AsmError::Parse {
-            lineno,
-            msg: "invalid loop index".to_owned(),
-        });
-    }
+fn expect_sound(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<u8> {
+    expect_byte(lineno, lex, operand, 1..=0xF)
+}
 
-    Ok(idx)
+fn expect_bool(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<bool> {
+    let n = expect_byte(lineno, lex, operand, 0..=1)?;
+    Ok(n != 0)
 }
 
-fn expect_sound(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<u8> {
-    const RANGE: std::ops::RangeInclusive<u8> = 1..=0xF;
+/// 数値を読み取り、range 内に収まっているか検査して u8 として返す。
+/// operand はエラーメッセージ用のオペランド名。
+fn expect_byte(
+    lineno: usize,
+    lex: &mut Lexer<Token>,
+    operand: &str,
+    range: std::ops::RangeInclusive<u8>,
+) -> AsmResult<u8> {
+    use std::convert::TryFrom;
 
-    let sound = expect_number(lineno, lex)?;
+    let value = expect_number(lineno, lex)?;
 
-    if !RANGE.contains(&sound) {
-        return Err(AsmError::Parse {
+    match u8::try_from(value) {
+        Ok(n) if range.contains(&n) => Ok(n),
+        _ => Err(AsmError::OutOfRange {
             lineno,
-            msg: format!("sound must be within {:?}: {}", RANGE, sound),
-        });
+            operand: operand.to_owned(),
+            min: i64::from(*range.start()),
+            max: i64::from(*range.end()),
+            value,
+        }),
     }
-
-    Ok(sound)
 }
 
-fn expect_bool(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<bool> {
-    let n = expect_number(lineno, lex)?;
+/// ビットマスクのオペランドを読み取る。
+/// 0..=255 に加えて、-128..=-1 を 2 の補数として受け付ける (-1 は 0xFF)。
+fn expect_mask(lineno: usize, lex: &mut Lexer<Token>, operand: &str) -> AsmResult<u8> {
+    let value = expect_number(lineno, lex)?;
 
-    if !(0..=1).contains(&n) {
-        return Err(AsmError::Parse {
+    match value {
+        0..=0xFF => Ok(value as u8),
+        -0x80..=-1 => Ok(value as i8 as u8),
+        _ => Err(AsmError::OutOfRange {
             lineno,
-            msg: format!("bool value must be 0 or 1: {}", lex.slice()),
-        });
+            operand: operand.to_owned(),
+            min: -0x80,
+            max: 0xFF,
+            value,
+        }),
     }
-
-    Ok(n != 0)
 }
 
-fn expect_number(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<u8> {
-    if let Some(Token::Number(addr)) = lex.next() {
-        Ok(addr)
-    } else {
-        Err(AsmError::Parse {
+fn expect_number(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<i64> {
+    match lex.next() {
+        Some(Token::Number(Some(n))) => Ok(n),
+        Some(Token::Number(None)) => Err(AsmError::Parse {
+            lineno,
+            msg: format!("number too large: {}", lex.slice()),
+        }),
+        _ => Err(AsmError::Parse {
             lineno,
             msg: format!("expected number, but got: {}", lex.slice()),
-        })
+        }),
     }
 }
 
@@ -542,7 +683,209 @@ fn expect_end(lineno: usize, lex: &mut Lexer<Token>) -> AsmResult<()> {
     }
 }
 
+/// 符号とプレフィックスを取り除いて数値リテラルをパースする。
+fn parse_number(s: &str, prefix: &str, radix: u32) -> Option<i64> {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let n = i64::from_str_radix(&s[prefix.len()..], radix).ok()?;
+
+    Some(if neg { -n } else { n })
+}
+
 fn trim_comment(s: &str) -> &str {
-    let pos = s.find(';').unwrap_or(s.len());
+    // 文字リテラル ';' はコメント開始とみなさない。
+    let pos = s
+        .match_indices(';')
+        .map(|(pos, _)| pos)
+        .find(|&pos| !(s[..pos].ends_with('\'') && s[pos + 1..].starts_with('\'')))
+        .unwrap_or(s.len());
     &s[..pos]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disasm;
+
+    /// アセンブルし、さらに逆アセンブル結果を再アセンブルしても同じバイト列になることを確かめる。
+    fn asm_round_trip(src: &str) -> Vec<u8> {
+        let code = asm(src.as_bytes()).unwrap();
+
+        let mut text = vec![];
+        disasm(&mut text, &code).unwrap();
+        assert_eq!(asm(text.as_slice()).unwrap(), code);
+
+        code
+    }
+
+    #[test]
+    fn anonymous_label_references() {
+        const SRC: &str = "\
+:
+move 0x01
+:
+move 0x02
+jump :--
+jump :-
+jump :+
+jump :++
+:
+move 0x03
+:
+move 0x04
+";
+        const EXPECTED: &str = "\
+L0:
+move 0x01
+L1:
+move 0x02
+jump L0
+jump L1
+jump L10
+jump L11
+L10:
+move 0x03
+L11:
+move 0x04
+";
+
+        assert_eq!(asm_round_trip(SRC), asm(EXPECTED.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn local_labels_are_scoped() {
+        const SRC: &str = "\
+A:
+.loop:
+move 0x01
+jump .loop
+B:
+move 0x02
+.loop:
+move 0x03
+jump .loop
+";
+        const EXPECTED: &str = "\
+A:
+move 0x01
+jump A
+B:
+move 0x02
+B_loop:
+move 0x03
+jump B_loop
+";
+
+        assert_eq!(asm_round_trip(SRC), asm(EXPECTED.as_bytes()).unwrap());
+
+        let assembly = assemble(SRC.as_bytes()).unwrap();
+        assert_eq!(assembly.labels["A.loop"], 0);
+        assert_eq!(assembly.labels["B.loop"], 4);
+    }
+
+    #[test]
+    fn qualified_local_label_references() {
+        const SRC: &str = "\
+A:
+.loop:
+move 0x01
+jump B.loop
+B:
+.loop:
+move 0x02
+jump A.loop
+";
+        const EXPECTED: &str = "\
+A:
+move 0x01
+jump B
+B:
+move 0x02
+jump A
+";
+
+        assert_eq!(asm_round_trip(SRC), asm(EXPECTED.as_bytes()).unwrap());
+        assert!(asm("A:\njump A.nothing\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn local_label_without_scope_is_rejected() {
+        assert!(asm(".loop:\njump .loop\n".as_bytes()).is_err());
+    }
+
+    fn asm_err(src: &str) -> String {
+        asm(src.as_bytes()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn number_syntaxes() {
+        const EXPECTED: &str = "set_position 0x20, 0x05\nset_part 0x41\n";
+
+        for src in &[
+            "set_position $20, %101\nset_part 'A'\n",
+            "set_position 32, 0b101\nset_part 65\n",
+            "set_position 0o40, 5\nset_part $41\n",
+        ] {
+            assert_eq!(
+                asm(src.as_bytes()).unwrap(),
+                asm(EXPECTED.as_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn negative_numbers() {
+        // マスクのみ 2 の補数として受け付ける。
+        assert_eq!(
+            asm("randomize_x -1\nrandomize_y -128\n".as_bytes()).unwrap(),
+            asm("randomize_x 0xFF\nrandomize_y 0x80\n".as_bytes()).unwrap()
+        );
+        assert_eq!(
+            asm_err("randomize_x -129\n"),
+            "line 1: randomize_x mask must be -128..=255, got -129"
+        );
+
+        assert_eq!(
+            asm_err("set_position -1, 0\n"),
+            "line 1: set_position x must be 0..=255, got -1"
+        );
+        assert_eq!(
+            asm_err("set_sprite -1\n"),
+            "line 1: set_sprite index must be 0..=15, got -1"
+        );
+    }
+
+    #[test]
+    fn out_of_range_messages() {
+        assert_eq!(
+            asm_err("set_position 300, 0\n"),
+            "line 1: set_position x must be 0..=255, got 300"
+        );
+        assert_eq!(
+            asm_err("move 0x15\nset_position 0, $100\n"),
+            "line 2: set_position y must be 0..=255, got 256"
+        );
+        assert_eq!(
+            asm_err("set_position 99999999999999999999, 0\n"),
+            "line 1: parse error: number too large: 99999999999999999999"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        const EXPECTED: &str = "set_part 0x3B\nmove 0x15\n";
+
+        // 文字リテラル ';' はコメントにならない。
+        const SRC: &str = "\
+; leading comment
+set_part ';'   ; trailing comment
+        move 0x15;no space
+";
+        assert_eq!(
+            asm(SRC.as_bytes()).unwrap(),
+            asm(EXPECTED.as_bytes()).unwrap()
+        );
+    }
+}
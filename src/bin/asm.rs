@@ -4,9 +4,18 @@ use starsoldier_bytecode as bytecode;
 
 #[derive(Debug, StructOpt)]
 struct Opt {
+    /// 出力形式
+    #[structopt(short, long, default_value = "bin", possible_values = bytecode::OutputFormat::NAMES)]
+    format: bytecode::OutputFormat,
+
+    /// 配列名/ラベル名 (省略時は入力ファイル名から生成)
+    #[structopt(short, long)]
+    name: Option<String>,
+
     #[structopt(parse(from_os_str))]
     path_in: std::path::PathBuf,
 
+    /// "-" なら標準出力に書き出す
     #[structopt(parse(from_os_str))]
     path_out: std::path::PathBuf,
 }
@@ -14,9 +23,43 @@ struct Opt {
 fn main() -> eyre::Result<()> {
     let opt = Opt::from_args();
 
-    let buf = bytecode::asm(std::fs::read(opt.path_in)?.as_slice())?;
+    let buf = bytecode::asm(std::fs::read(&opt.path_in)?.as_slice())?;
 
-    std::fs::write(opt.path_out, buf)?;
+    let name = match opt.name {
+        Some(name) => name,
+        None => default_name(&opt.path_in, opt.format),
+    };
+
+    if opt.path_out.as_os_str() == "-" {
+        let wtr = std::io::stdout();
+        let wtr = std::io::BufWriter::new(wtr.lock());
+        bytecode::write_output(wtr, &buf, opt.format, &name)?;
+    } else {
+        let wtr = std::io::BufWriter::new(std::fs::File::create(opt.path_out)?);
+        bytecode::write_output(wtr, &buf, opt.format, &name)?;
+    }
 
     Ok(())
 }
+
+/// 入力ファイル名から識別子として使える名前を生成する。
+fn default_name(path: &std::path::Path, format: bytecode::OutputFormat) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+
+    if matches!(format, bytecode::OutputFormat::Rust) {
+        name.make_ascii_uppercase();
+    }
+
+    name
+}
@@ -0,0 +1,160 @@
+//! 複数のバイナリで共通のオプションと処理。
+
+use structopt::StructOpt;
+
+use starsoldier_bytecode as bytecode;
+
+/// .asm から読み込んだ場合のソーステキストとアセンブル結果。
+pub type Listing = (String, bytecode::Assembly);
+
+// スクリプトと InterpreterInit のオプション。
+// flatten される構造体の doc コメントは各バイナリの about になってしまうので、通常のコメントにする。
+#[derive(Debug, StructOpt)]
+pub struct ScriptOpt {
+    #[structopt(long)]
+    pub boss: bool,
+    #[structopt(long, default_value = "1")]
+    pub difficulty: u8,
+    #[structopt(long)]
+    pub shot_with_rank: bool,
+    #[structopt(long)]
+    pub accel_shot_with_rank: bool,
+    #[structopt(long)]
+    pub homing_shot_with_rank: bool,
+    #[structopt(long)]
+    pub extra_act_with_rank: bool,
+    #[structopt(long)]
+    pub accel_with_rank: bool,
+
+    /// 開始アドレス
+    #[structopt(long, default_value = "0")]
+    pub pc: usize,
+    #[structopt(long, default_value = "128")]
+    pub x: u8,
+    #[structopt(long, default_value = "0")]
+    pub y: u8,
+
+    /// 1 フレームに実行できる命令数の上限
+    #[structopt(long, default_value = "256")]
+    pub op_budget: usize,
+
+    /// .asm ならアセンブリ、さもなくばバイナリとして読み込む
+    #[structopt(parse(from_os_str))]
+    pub path_in: std::path::PathBuf,
+}
+
+impl ScriptOpt {
+    /// スクリプトを読み込む。.asm ならアセンブルし、ソースとデバッグ情報も返す。
+    pub fn load(&self) -> eyre::Result<(Vec<u8>, Option<Listing>)> {
+        let is_asm = matches!(
+            self.path_in.extension(),
+            Some(ext) if ext.eq_ignore_ascii_case("asm")
+        );
+
+        if is_asm {
+            let text = std::fs::read_to_string(&self.path_in)?;
+            let assembly = bytecode::assemble(text.as_bytes())?;
+            Ok((assembly.code.clone(), Some((text, assembly))))
+        } else {
+            Ok((std::fs::read(&self.path_in)?, None))
+        }
+    }
+
+    /// オプションに従った InterpreterInit を返す。rank は 0、clip は ClipConfig::ROM になる。
+    pub fn interpreter_init(&self, program: Vec<u8>) -> bytecode::InterpreterInit {
+        bytecode::InterpreterInit {
+            program,
+            pc: self.pc,
+
+            boss: self.boss,
+            difficulty: self.difficulty,
+            shot_with_rank: self.shot_with_rank,
+            accel_shot_with_rank: self.accel_shot_with_rank,
+            homing_shot_with_rank: self.homing_shot_with_rank,
+            extra_act_with_rank: self.extra_act_with_rank,
+            accel_with_rank: self.accel_with_rank,
+            rank: 0,
+
+            x: self.x,
+            y: self.y,
+
+            op_budget: Some(self.op_budget),
+            clip: bytecode::ClipConfig::ROM,
+        }
+    }
+}
+
+// 自機モデルのオプション。
+#[derive(Debug, StructOpt)]
+pub struct HeroOpt {
+    /// 自機の動き (stationary, waypoints, recorded, dodge)
+    #[structopt(long = "hero", default_value = "stationary", possible_values = &["stationary", "waypoints", "recorded", "dodge"])]
+    pub kind: String,
+
+    /// 自機の初期位置 (stationary, dodge)
+    #[structopt(long, default_value = "128")]
+    pub hero_x: u8,
+    #[structopt(long, default_value = "200")]
+    pub hero_y: u8,
+
+    /// --hero waypoints の経由点 ("x,y x,y ...")
+    #[structopt(long)]
+    pub waypoints: Option<String>,
+
+    /// 経由点を辿り終えたら最初の経由点へ戻る
+    #[structopt(long)]
+    pub loop_waypoints: bool,
+
+    /// --hero recorded で再生する自機位置のファイル ((x, y) のバイト対の列)
+    #[structopt(long, parse(from_os_str))]
+    pub hero_file: Option<std::path::PathBuf>,
+
+    /// 自機の 1 フレームの移動量 (waypoints, dodge)
+    #[structopt(long, default_value = "2")]
+    pub hero_speed: u8,
+
+    /// --hero dodge で弾を避け始める距離
+    #[structopt(long, default_value = "24")]
+    pub danger_radius: u8,
+}
+
+impl HeroOpt {
+    /// オプションで指定された自機モデルを作る。
+    pub fn make_hero(&self) -> eyre::Result<Box<dyn bytecode::HeroModel>> {
+        let hero: Box<dyn bytecode::HeroModel> = match self.kind.as_str() {
+            "waypoints" => {
+                let waypoints = self
+                    .waypoints
+                    .as_deref()
+                    .ok_or_else(|| eyre::eyre!("--hero waypoints requires --waypoints"))?;
+                Box::new(bytecode::WaypointHero::new(
+                    bytecode::parse_waypoints(waypoints)?,
+                    self.hero_speed,
+                    self.loop_waypoints,
+                ))
+            }
+            "recorded" => {
+                let path = self
+                    .hero_file
+                    .as_ref()
+                    .ok_or_else(|| eyre::eyre!("--hero recorded requires --hero-file"))?;
+                Box::new(
+                    bytecode::RecordedHero::from_bytes(&std::fs::read(path)?)
+                        .ok_or_else(|| eyre::eyre!("{}: no hero position", path.display()))?,
+                )
+            }
+            "dodge" => Box::new(bytecode::DodgingHero::new(
+                self.hero_x,
+                self.hero_y,
+                self.hero_speed,
+                self.danger_radius,
+            )),
+            _ => Box::new(bytecode::StationaryHero {
+                x: self.hero_x,
+                y: self.hero_y,
+            }),
+        };
+
+        Ok(hero)
+    }
+}
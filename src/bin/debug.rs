@@ -0,0 +1,583 @@
+//! 対話的デバッガ。
+//!
+//! アセンブリ (.asm) またはバイナリを読み込み、フレーム単位/命令単位で実行する。
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead as _, Write as _};
+
+use structopt::StructOpt;
+
+use starsoldier_bytecode as bytecode;
+
+mod common;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    #[structopt(flatten)]
+    script: common::ScriptOpt,
+
+    #[structopt(flatten)]
+    hero: common::HeroOpt,
+
+    #[structopt(long, default_value = "0")]
+    rank: u8,
+
+    /// このフレーム数ごとにランクを 1 上げる (最大 7)
+    #[structopt(long)]
+    rank_rise_interval: Option<usize>,
+
+    /// 面 (1..=16)
+    #[structopt(long, default_value = "1")]
+    stage: u8,
+    #[structopt(long)]
+    second_round: bool,
+
+    /// 指定フレームの終わりに敵へダメージを与える ("30,45,60")。敵はスロット 0 のみ
+    #[structopt(long, default_value = "")]
+    damage_at: bytecode::DamageSchedule,
+
+    /// 乱数のシード
+    #[structopt(long, default_value = "1")]
+    seed: u16,
+}
+
+/// 自機位置は自機モデルに従う。乱数は xorshift で生成し、発生したイベントは表示する。
+type DebugGame =
+    bytecode::GameParts<bytecode::FixedEnvironment, bytecode::SeededRng, ShotPrinter, SoundPrinter>;
+
+/// 発射要求を表示し、敵弾として追加する (自機モデルが弾を避けられるように)。
+#[derive(Debug)]
+struct ShotPrinter {
+    bullets: bytecode::Bullets,
+}
+
+impl bytecode::Shooter for ShotPrinter {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        println!(
+            "  event: shoot_aim at ({}, {}), speed_mask={:#04X}, force_homing={}",
+            x, y, speed_mask, force_homing
+        );
+        self.bullets.shoot_aim(x, y, speed_mask, force_homing);
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: bytecode::Direction) {
+        println!(
+            "  event: shoot_direction at ({}, {}), dir={:#04X}",
+            x,
+            y,
+            dir.index()
+        );
+        self.bullets.shoot_direction(x, y, dir);
+    }
+}
+
+/// サウンド要求を表示する。
+#[derive(Debug)]
+struct SoundPrinter;
+
+impl bytecode::Audio for SoundPrinter {
+    fn restore_music(&mut self) {
+        println!("  event: restore_music");
+    }
+    fn play_sound(&mut self, sound: u8) {
+        println!("  event: play_sound {}", sound);
+    }
+
+    fn play_damage_sound(&mut self) {
+        println!("  event: damage sound");
+    }
+    fn play_destruction_sound(&mut self) {
+        println!("  event: destruction sound");
+    }
+}
+
+#[derive(Debug)]
+struct Source {
+    lines: Vec<String>,
+    addr_to_lineno: BTreeMap<usize, usize>,
+    labels: HashMap<String, u8>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Watch {
+    X,
+    Y,
+    Health,
+    Sprite,
+}
+
+impl Watch {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "x" => Some(Self::X),
+            "y" => Some(Self::Y),
+            "health" => Some(Self::Health),
+            "sprite" => Some(Self::Sprite),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::X => "x",
+            Self::Y => "y",
+            Self::Health => "health",
+            Self::Sprite => "sprite",
+        }
+    }
+
+    fn get(self, interp: &bytecode::Interpreter) -> u8 {
+        match self {
+            Self::X => interp.x(),
+            Self::Y => interp.y(),
+            Self::Health => interp.health(),
+            Self::Sprite => interp.sprite_index(),
+        }
+    }
+}
+
+struct Debugger {
+    interp: bytecode::Interpreter,
+    game: DebugGame,
+    hero: Box<dyn bytecode::HeroModel>,
+    damage_schedule: bytecode::DamageSchedule,
+    rank_model: Option<bytecode::RankModel>,
+    source: Option<Source>,
+
+    // 終えたフレーム数。フレームの途中なら、実行中のフレームは frame + 1 番目。
+    frame: usize,
+
+    breakpoints: Vec<usize>,
+    watches: Vec<Watch>,
+}
+
+impl Debugger {
+    /// 処理を 1 つ (命令 1 つ、ホーミング移動、またはスリープのカウントダウン) 実行する。
+    /// フレームの最初なら自機と敵弾を進め、フレームを終えたら後処理を行う。
+    /// 停止すべき場合は true を返す。
+    fn exec_action(&mut self, print_trace: bool) -> bool {
+        if !self.interp.is_mid_frame() {
+            if !matches!(self.interp.state(), bytecode::EnemyState::Alive) {
+                println!("enemy is not alive: {:?}", self.interp.state());
+                return true;
+            }
+            self.begin_frame();
+        }
+
+        let trace = match self.interp.step_op(&mut self.game) {
+            Ok(trace) => trace,
+            Err(e) => {
+                println!("frame {}: error: {}", self.frame + 1, e);
+                self.end_frame();
+                return true;
+            }
+        };
+        if print_trace {
+            self.print_trace(&trace);
+        }
+
+        if trace.yield_reason.is_some() {
+            self.end_frame()
+        } else {
+            false
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        let (hero_x, hero_y) = self.hero.position();
+        self.game.env.hero_x = hero_x;
+        self.game.env.hero_y = hero_y;
+        self.game.env.rank = self.rank_model.as_ref().map(bytecode::RankModel::rank);
+
+        let bullets = &mut self.game.shooter.bullets;
+        bullets.set_target(hero_x, hero_y);
+        bullets.step();
+    }
+
+    /// フレームの後処理。停止すべき場合は true を返す。
+    fn end_frame(&mut self) -> bool {
+        self.hero.step(self.game.shooter.bullets.bullets());
+        if let Some(rank_model) = &mut self.rank_model {
+            rank_model.on_frame();
+        }
+        self.frame += 1;
+
+        if self.damage_schedule.hits(self.frame).contains(&0)
+            && matches!(self.interp.state(), bytecode::EnemyState::Alive)
+        {
+            println!("frame {}: scheduled damage", self.frame);
+            if let Err(e) = self.interp.damage(&mut self.game) {
+                println!("frame {}: error: {}", self.frame, e);
+                return true;
+            }
+        }
+
+        if !matches!(self.interp.state(), bytecode::EnemyState::Alive) {
+            println!(
+                "frame {}: enemy state: {:?}",
+                self.frame,
+                self.interp.state()
+            );
+            return true;
+        }
+
+        false
+    }
+
+    /// 次に実行する命令にブレークポイントが設定されていれば true を返す。
+    fn at_breakpoint(&self) -> bool {
+        self.interp.will_execute_op() && self.breakpoints.contains(&self.interp.pc())
+    }
+
+    /// frames 個のフレームを終えるまで (None なら無制限に) 実行する。
+    /// ブレークポイント、ウォッチポイント、敵の消滅で停止する。
+    /// フレームの途中から始めた場合、そのフレームも 1 つと数える。
+    fn run(&mut self, frames: Option<usize>) {
+        const ACTION_MAX: usize = 10_000_000;
+
+        let mut frames_done = 0;
+        for i in 0..ACTION_MAX {
+            if matches!(frames, Some(n) if frames_done >= n) {
+                self.print_position();
+                return;
+            }
+
+            // 停止位置から再開する場合、そのブレークポイントでは止まらない。
+            if i > 0 && self.at_breakpoint() {
+                println!(
+                    "frame {}: breakpoint at {:#04X}",
+                    self.frame + 1,
+                    self.interp.pc()
+                );
+                return;
+            }
+
+            let values_before: Vec<_> = self.watches.iter().map(|w| w.get(&self.interp)).collect();
+            let addr = self.interp.pc();
+            let frame = self.frame;
+
+            let mut stop = self.exec_action(false);
+
+            for (watch, before) in self.watches.iter().zip(values_before) {
+                let after = watch.get(&self.interp);
+                if before != after {
+                    println!(
+                        "frame {}: watch {}: {} -> {} (at {:#04X})",
+                        frame + 1,
+                        watch.name(),
+                        before,
+                        after,
+                        addr
+                    );
+                    stop = true;
+                }
+            }
+
+            if self.frame > frame {
+                frames_done += 1;
+            }
+            if stop {
+                self.print_position();
+                return;
+            }
+        }
+        println!("stopped after {} actions", ACTION_MAX);
+        self.print_position();
+    }
+
+    fn step_op(&mut self) {
+        self.exec_action(true);
+    }
+
+    fn print_trace(&self, trace: &bytecode::OpTrace) {
+        print!(
+            "frame {}: {:#04X}: {:<24} ({}, {}) -> ({}, {})",
+            self.frame + 1,
+            trace.addr,
+            trace.action.to_string(),
+            trace.pos_before.0,
+            trace.pos_before.1,
+            trace.pos_after.0,
+            trace.pos_after.1
+        );
+        if let Some(dir) = trace.dir {
+            print!(" dir={:#04X}", dir.index());
+        }
+        if let Some(reason) = trace.yield_reason {
+            print!(" yield={:?}", reason);
+        }
+        println!();
+    }
+
+    fn print_position(&self) {
+        let (frame, mid_frame) = if self.interp.is_mid_frame() {
+            (self.frame + 1, " (mid-frame)")
+        } else {
+            (self.frame, "")
+        };
+        println!(
+            "frame {}{}: pc={:#04X} pos=({}, {}) state={:?}",
+            frame,
+            mid_frame,
+            self.interp.pc(),
+            self.interp.x(),
+            self.interp.y(),
+            self.interp.state()
+        );
+    }
+
+    fn print_state(&self) {
+        let interp = &self.interp;
+        let (inv_x, inv_y) = interp.inversion();
+
+        println!("frame:          {}", self.frame);
+        println!("state:          {:?}", interp.state());
+        println!("pc:             {:#04X}", interp.pc());
+        println!("position:       ({}, {})", interp.x(), interp.y());
+        println!(
+            "hero:           ({}, {})",
+            self.game.env.hero_x, self.game.env.hero_y
+        );
+        println!(
+            "bullets:        {}",
+            self.game.shooter.bullets.bullets().len()
+        );
+        println!("inversion:      ({}, {})", inv_x, inv_y);
+        println!("rank:           {}", interp.rank());
+        println!("health:         {}", interp.health());
+        println!("sprite:         {}", interp.sprite_index());
+        println!("part:           {}", interp.part());
+        println!("sleep_timer:    {}", interp.sleep_timer());
+        println!("homing_timer:   {}", interp.homing_timer());
+        println!(
+            "loop:           start={:#04X} counter={}",
+            interp.loop_start_addr(),
+            interp.loop_counter()
+        );
+        println!("jump_on_damage: {:#04X}", interp.jump_on_damage());
+        println!("mid-frame:      {}", interp.is_mid_frame());
+    }
+
+    fn list(&self, context: usize) {
+        let program = self.interp.program();
+
+        let mut ops = vec![];
+        let mut addr = 0;
+        while addr < program.len() {
+            match bytecode::Op::decode(&program[addr..]) {
+                Ok(op) => {
+                    ops.push((addr, Some(op)));
+                    addr += op.len();
+                }
+                Err(_) => {
+                    ops.push((addr, None));
+                    addr += 1;
+                }
+            }
+        }
+
+        let current = self.interp.pc();
+        let idx = ops
+            .iter()
+            .rposition(|&(addr, _)| addr <= current)
+            .unwrap_or(0);
+        let start = idx.saturating_sub(context);
+        let end = (idx + context + 1).min(ops.len());
+
+        for &(addr, op) in &ops[start..end] {
+            if let Some(source) = &self.source {
+                let mut labels: Vec<_> = source
+                    .labels
+                    .iter()
+                    .filter(|(_, &a)| usize::from(a) == addr)
+                    .map(|(label, _)| label.as_str())
+                    .collect();
+                labels.sort_unstable();
+                for label in labels {
+                    println!("      {}:", label);
+                }
+            }
+
+            let marker = if addr == current { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&addr) {
+                "*"
+            } else {
+                " "
+            };
+            let text = op.map_or_else(|| "(undefined)".to_owned(), |op| op.to_string());
+            print!("{}{} {:#04X}: {:<24}", marker, bp, addr, text);
+
+            if let Some(source) = &self.source {
+                if let Some(&lineno) = source.addr_to_lineno.get(&addr) {
+                    print!(" ; {:>4}: {}", lineno, source.lines[lineno - 1].trim());
+                }
+            }
+            println!();
+        }
+    }
+
+    fn parse_addr(&self, s: &str) -> Option<usize> {
+        if let Some(source) = &self.source {
+            if let Some(&addr) = source.labels.get(s) {
+                return Some(usize::from(addr));
+            }
+        }
+
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+            usize::from_str_radix(hex, 16).ok()
+        } else {
+            s.parse().ok()
+        }
+    }
+
+    /// コマンドを 1 つ実行する。終了すべき場合は false を返す。
+    fn exec(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let cmd = match tokens.next() {
+            Some(cmd) => cmd,
+            None => return true,
+        };
+        let arg = tokens.next();
+
+        match cmd {
+            "s" | "step" => {
+                let n = arg.and_then(|s| s.parse().ok()).unwrap_or(1);
+                self.run(Some(n));
+            }
+            "si" | "stepi" => self.step_op(),
+            "c" | "continue" => self.run(None),
+            "b" | "break" => match arg {
+                Some(s) => match self.parse_addr(s) {
+                    Some(addr) => {
+                        if !self.breakpoints.contains(&addr) {
+                            self.breakpoints.push(addr);
+                        }
+                        println!("breakpoint at {:#04X}", addr);
+                    }
+                    None => println!("invalid address: {}", s),
+                },
+                None => {
+                    for addr in &self.breakpoints {
+                        println!("breakpoint at {:#04X}", addr);
+                    }
+                }
+            },
+            "d" | "delete" => match arg.and_then(|s| self.parse_addr(s)) {
+                Some(addr) => self.breakpoints.retain(|&a| a != addr),
+                None => self.breakpoints.clear(),
+            },
+            "w" | "watch" => match arg {
+                Some(s) => match Watch::parse(s) {
+                    Some(watch) => {
+                        if !self.watches.contains(&watch) {
+                            self.watches.push(watch);
+                        }
+                    }
+                    None => println!("watch target must be one of: x, y, health, sprite"),
+                },
+                None => {
+                    for watch in &self.watches {
+                        println!("watch {}", watch.name());
+                    }
+                }
+            },
+            "uw" | "unwatch" => match arg.and_then(Watch::parse) {
+                Some(watch) => self.watches.retain(|&w| w != watch),
+                None => self.watches.clear(),
+            },
+            "p" | "print" => self.print_state(),
+            "l" | "list" => {
+                let context = arg.and_then(|s| s.parse().ok()).unwrap_or(5);
+                self.list(context);
+            }
+            "h" | "help" => print_help(),
+            "q" | "quit" => return false,
+            _ => println!("unknown command: {} (h for help)", cmd),
+        }
+
+        true
+    }
+}
+
+fn print_help() {
+    println!("s, step [N]        run N frames (default 1; the rest of the frame if mid-frame)");
+    println!("si, stepi          execute a single op (or homing move / sleep countdown)");
+    println!("c, continue        run until a breakpoint/watchpoint hits or the enemy is gone");
+    println!("b, break [ADDR]    set a breakpoint at an address or label (list if omitted)");
+    println!("d, delete [ADDR]   delete a breakpoint (all if omitted)");
+    println!("w, watch [NAME]    watch x, y, health or sprite (list if omitted)");
+    println!("uw, unwatch [NAME] delete a watchpoint (all if omitted)");
+    println!("p, print           print interpreter state");
+    println!("l, list [N]        disassemble N ops around pc (default 5)");
+    println!("q, quit            quit");
+}
+
+fn main() -> eyre::Result<()> {
+    let opt = Opt::from_args();
+    // 敵は 1 体 (スロット 0) だけなので、他のスロットへの予定は受け付けない。
+    if let Some(slot) = opt.damage_at.slots().find(|&slot| slot != 0) {
+        eyre::bail!("--damage-at: slot {} does not exist (only slot 0)", slot);
+    }
+
+    let (program, source) = opt.script.load()?;
+    let source = source.map(|(text, assembly)| Source {
+        lines: text.lines().map(str::to_owned).collect(),
+        addr_to_lineno: assembly.addr_to_lineno,
+        labels: assembly.labels,
+    });
+
+    let interp = bytecode::InterpreterInit {
+        rank: opt.rank,
+        ..opt.script.interpreter_init(program)
+    }
+    .init()?;
+
+    let game = DebugGame {
+        env: bytecode::FixedEnvironment {
+            stage: opt.stage,
+            second_round: opt.second_round,
+            hero_x: opt.hero.hero_x,
+            hero_y: opt.hero.hero_y,
+            rank: None,
+        },
+        rng: bytecode::SeededRng::new(opt.seed),
+        shooter: ShotPrinter {
+            bullets: bytecode::Bullets::new(bytecode::BulletConfig::default()),
+        },
+        audio: SoundPrinter,
+    };
+
+    let hero = opt.hero.make_hero()?;
+
+    let mut dbg = Debugger {
+        interp,
+        game,
+        hero,
+        damage_schedule: opt.damage_at.clone(),
+        rank_model: opt
+            .rank_rise_interval
+            .map(|interval| bytecode::RankModel::new(opt.rank, Some(interval), None, 0)),
+        source,
+        frame: 0,
+        breakpoints: vec![],
+        watches: vec![],
+    };
+
+    dbg.list(5);
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(dbg) ");
+        std::io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if !dbg.exec(&line) {
+            break;
+        }
+    }
+
+    Ok(())
+}
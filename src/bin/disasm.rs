@@ -4,6 +4,10 @@ use starsoldier_bytecode as bytecode;
 
 #[derive(Debug, StructOpt)]
 struct Opt {
+    /// 入力を ca65/asm6 形式の .byte リストとして読み込む
+    #[structopt(long)]
+    listing: bool,
+
     #[structopt(parse(from_os_str))]
     path_in: std::path::PathBuf,
 }
@@ -13,13 +17,20 @@ fn main() -> eyre::Result<()> {
 
     let opt = Opt::from_args();
 
+    let wtr = std::io::stdout();
+    let wtr = std::io::BufWriter::new(wtr.lock());
+
+    if opt.listing {
+        let rdr = std::fs::File::open(opt.path_in)?;
+        bytecode::import_listing(rdr, wtr)?;
+        return Ok(());
+    }
+
     let buf = std::fs::read(opt.path_in)?;
     if buf.len() > BUF_LEN_MAX {
         eprintln!("warning: buffer length exceeds {}", BUF_LEN_MAX);
     }
 
-    let wtr = std::io::stdout();
-    let wtr = std::io::BufWriter::new(wtr.lock());
     bytecode::disasm(wtr, &buf)?;
 
     Ok(())
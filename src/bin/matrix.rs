@@ -0,0 +1,434 @@
+//! スクリプトをランク 0..=7 × 面 1..=16 × 周回の全組み合わせで実行し、挙動の違いを表にまとめる。
+
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use structopt::StructOpt;
+
+use starsoldier_bytecode as bytecode;
+
+mod common;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    #[structopt(flatten)]
+    script: common::ScriptOpt,
+
+    #[structopt(flatten)]
+    hero: common::HeroOpt,
+
+    /// 5 つの *_with_rank フラグの全組み合わせについて、挙動が何通りに分かれるかを要約する
+    #[structopt(long)]
+    sweep_flags: bool,
+
+    /// 乱数のシード
+    #[structopt(long, default_value = "1")]
+    seed: u16,
+
+    /// 最大フレーム数 (敵が消滅したらそこで終了)
+    #[structopt(long, default_value = "1000")]
+    frames: usize,
+
+    /// 表の代わりに全組み合わせの結果を CSV で出力する
+    #[structopt(long)]
+    csv: bool,
+
+    /// 出力先 (省略時は標準出力)
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+}
+
+/// *_with_rank フラグの組。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Flags {
+    shot: bool,
+    accel_shot: bool,
+    homing_shot: bool,
+    extra_act: bool,
+    accel: bool,
+}
+
+impl Flags {
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            shot: bits & 1 != 0,
+            accel_shot: bits & 2 != 0,
+            homing_shot: bits & 4 != 0,
+            extra_act: bits & 8 != 0,
+            accel: bits & 16 != 0,
+        }
+    }
+
+    fn names(self) -> String {
+        let names: Vec<_> = [
+            (self.shot, "shot"),
+            (self.accel_shot, "accel_shot"),
+            (self.homing_shot, "homing_shot"),
+            (self.extra_act, "extra_act"),
+            (self.accel, "accel"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect();
+
+        if names.is_empty() {
+            "(none)".to_owned()
+        } else {
+            names.join("+")
+        }
+    }
+}
+
+/// 1 回の実行結果。これが等しい組み合わせは同じ挙動とみなす。
+#[derive(Debug, Eq, PartialEq)]
+struct Run {
+    trajectory: Vec<(u8, u8)>,
+    shots: Vec<(usize, bytecode::Event)>,
+    lifetime: usize,
+    end: Option<bytecode::EnemyState>, // None なら最大フレーム数まで生存
+    error: Option<String>,
+}
+
+impl Run {
+    fn homing_count(&self) -> usize {
+        self.shots
+            .iter()
+            .filter(|(_, event)| {
+                matches!(
+                    event,
+                    bytecode::Event::ShootAim {
+                        force_homing: true,
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    /// 弾のスピード指定マスクの集合。
+    fn speed_masks(&self) -> BTreeSet<u8> {
+        self.shots
+            .iter()
+            .filter_map(|(_, event)| match *event {
+                bytecode::Event::ShootAim { speed_mask, .. } => Some(speed_mask),
+                bytecode::Event::ShootDirection { dir, .. } => Some(dir.index() & 0x30),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn end_name(&self) -> String {
+        match (&self.error, self.end) {
+            (Some(_), _) => "Error".to_owned(),
+            (None, Some(state)) => format!("{:?}", state),
+            (None, None) => "Alive".to_owned(),
+        }
+    }
+
+    /// 要約。base が与えられれば、それとの違いも述べる。
+    fn describe(&self, base: Option<&Self>) -> String {
+        let masks: Vec<_> = self
+            .speed_masks()
+            .iter()
+            .map(|mask| format!("{:#04X}", mask))
+            .collect();
+        let mut desc = format!(
+            "lifetime {} ({}), {} shots ({} homing, speed masks [{}])",
+            self.lifetime,
+            self.end_name(),
+            self.shots.len(),
+            self.homing_count(),
+            masks.join(" ")
+        );
+
+        if let Some(base) = base {
+            let diverge = self
+                .trajectory
+                .iter()
+                .zip(&base.trajectory)
+                .position(|(a, b)| a != b)
+                .or_else(|| {
+                    (self.trajectory.len() != base.trajectory.len())
+                        .then(|| self.trajectory.len().min(base.trajectory.len()))
+                });
+            match diverge {
+                Some(frame) => desc.push_str(&format!(", path differs from A at frame {}", frame)),
+                None => desc.push_str(", same path as A"),
+            }
+        }
+
+        if let Some(e) = &self.error {
+            desc.push_str(&format!(", error: {}", e));
+        }
+
+        desc
+    }
+}
+
+#[derive(Debug)]
+struct Cell {
+    second_round: bool,
+    stage: u8,
+    rank: u8,
+    class: usize,
+}
+
+/// 全組み合わせの実行結果。挙動の種類 (class) ごとに代表の Run を持つ。
+#[derive(Debug)]
+struct Matrix {
+    cells: Vec<Cell>,
+    classes: Vec<Run>,
+}
+
+const STAGES: std::ops::RangeInclusive<u8> = 1..=16;
+const RANKS: std::ops::RangeInclusive<u8> = 0..=7;
+
+fn run_matrix(opt: &Opt, program: &[u8], flags: Flags) -> eyre::Result<Matrix> {
+    let mut matrix = Matrix {
+        cells: vec![],
+        classes: vec![],
+    };
+
+    for &second_round in &[false, true] {
+        for stage in STAGES {
+            for rank in RANKS {
+                let run = run_once(opt, program, flags, second_round, stage, rank)?;
+                let class = match matrix.classes.iter().position(|c| *c == run) {
+                    Some(class) => class,
+                    None => {
+                        matrix.classes.push(run);
+                        matrix.classes.len() - 1
+                    }
+                };
+                matrix.cells.push(Cell {
+                    second_round,
+                    stage,
+                    rank,
+                    class,
+                });
+            }
+        }
+    }
+
+    Ok(matrix)
+}
+
+fn run_once(
+    opt: &Opt,
+    program: &[u8],
+    flags: Flags,
+    second_round: bool,
+    stage: u8,
+    rank: u8,
+) -> eyre::Result<Run> {
+    let mut interp = bytecode::InterpreterInit {
+        shot_with_rank: flags.shot,
+        accel_shot_with_rank: flags.accel_shot,
+        homing_shot_with_rank: flags.homing_shot,
+        extra_act_with_rank: flags.extra_act,
+        accel_with_rank: flags.accel,
+        rank,
+        ..opt.script.interpreter_init(program.to_vec())
+    }
+    .init()?;
+
+    let mut hero = opt.hero.make_hero()?;
+    let (hero_x, hero_y) = hero.position();
+
+    let mut input = bytecode::GameParts {
+        env: bytecode::FixedEnvironment {
+            stage,
+            second_round,
+            hero_x,
+            hero_y,
+            rank: None,
+        },
+        rng: bytecode::SeededRng::new(opt.seed),
+        shooter: (),
+        audio: (),
+    };
+
+    // --hero dodge が避けられるように敵弾も動かす。
+    let mut bullets = bytecode::Bullets::new(bytecode::BulletConfig::default());
+
+    let mut run = Run {
+        trajectory: vec![(interp.x(), interp.y())],
+        shots: vec![],
+        lifetime: 0,
+        end: None,
+        error: None,
+    };
+    for frame in 1..=opt.frames {
+        let (hero_x, hero_y) = hero.position();
+        input.env.hero_x = hero_x;
+        input.env.hero_y = hero_y;
+
+        // 既存の弾を動かしてから、このフレームで撃たれた弾を追加する。
+        bullets.set_target(hero_x, hero_y);
+        bullets.step();
+        let mut events = vec![];
+        let res = interp.step_events(&mut input, &mut events);
+        for event in &events {
+            bullets.shoot_event(event);
+        }
+        hero.step(bullets.bullets());
+        run.lifetime = frame;
+        run.trajectory.push((interp.x(), interp.y()));
+        run.shots.extend(
+            events
+                .into_iter()
+                .filter(|event| {
+                    matches!(
+                        event,
+                        bytecode::Event::ShootAim { .. } | bytecode::Event::ShootDirection { .. }
+                    )
+                })
+                .map(|event| (frame, event)),
+        );
+        if let Err(e) = res {
+            run.error = Some(e.to_string());
+            break;
+        }
+        if !matches!(interp.state(), bytecode::EnemyState::Alive) {
+            run.end = Some(interp.state());
+            break;
+        }
+    }
+
+    Ok(run)
+}
+
+/// class 番号を表のラベル (A, B, ..., Z, AA, AB, ...) にする。
+fn class_label(mut class: usize) -> String {
+    let mut label = vec![];
+    loop {
+        label.push(b'A' + (class % 26) as u8);
+        if class < 26 {
+            break;
+        }
+        class = class / 26 - 1;
+    }
+    label.reverse();
+    String::from_utf8(label).unwrap()
+}
+
+fn write_table<W: Write>(mut wtr: W, matrix: &Matrix) -> std::io::Result<()> {
+    for &second_round in &[false, true] {
+        writeln!(
+            wtr,
+            "round {} (rows: rank, columns: stage)",
+            if second_round { 2 } else { 1 }
+        )?;
+        write!(wtr, "    ")?;
+        for stage in STAGES {
+            write!(wtr, "{:>3}", stage)?;
+        }
+        writeln!(wtr)?;
+
+        for rank in RANKS {
+            write!(wtr, "{:>4}", rank)?;
+            for stage in STAGES {
+                let cell = matrix
+                    .cells
+                    .iter()
+                    .find(|c| c.second_round == second_round && c.stage == stage && c.rank == rank)
+                    .expect("every combination must be run");
+                write!(wtr, "{:>3}", class_label(cell.class))?;
+            }
+            writeln!(wtr)?;
+        }
+        writeln!(wtr)?;
+    }
+
+    writeln!(wtr, "{} distinct behavior(s):", matrix.classes.len())?;
+    for (class, run) in matrix.classes.iter().enumerate() {
+        let base = (class > 0).then(|| &matrix.classes[0]);
+        writeln!(wtr, "  {}: {}", class_label(class), run.describe(base))?;
+    }
+
+    Ok(())
+}
+
+fn write_csv<W: Write>(mut wtr: W, matrix: &Matrix) -> std::io::Result<()> {
+    writeln!(
+        wtr,
+        "round,stage,rank,class,lifetime,end,shots,homing_shots"
+    )?;
+    for cell in &matrix.cells {
+        let run = &matrix.classes[cell.class];
+        writeln!(
+            wtr,
+            "{},{},{},{},{},{},{},{}",
+            if cell.second_round { 2 } else { 1 },
+            cell.stage,
+            cell.rank,
+            class_label(cell.class),
+            run.lifetime,
+            run.end_name(),
+            run.shots.len(),
+            run.homing_count()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// フラグの全組み合わせについて、挙動の種類数と基準 (全フラグ off) からの変化を要約する。
+fn write_sweep<W: Write>(mut wtr: W, opt: &Opt, program: &[u8]) -> eyre::Result<()> {
+    let base = run_matrix(opt, program, Flags::from_bits(0))?;
+
+    writeln!(wtr, "flags,distinct_behaviors,cells_changed_from_no_flags")?;
+    for bits in 0..32 {
+        let flags = Flags::from_bits(bits);
+        let matrix = run_matrix(opt, program, flags)?;
+        let changed = matrix
+            .cells
+            .iter()
+            .zip(&base.cells)
+            .filter(|(cell, base_cell)| matrix.classes[cell.class] != base.classes[base_cell.class])
+            .count();
+        writeln!(
+            wtr,
+            "{},{},{}",
+            flags.names(),
+            matrix.classes.len(),
+            changed
+        )?;
+    }
+
+    Ok(())
+}
+
+fn main() -> eyre::Result<()> {
+    let opt = Opt::from_args();
+
+    let (program, _) = opt.script.load()?;
+
+    let wtr: Box<dyn Write> = match &opt.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut wtr = std::io::BufWriter::new(wtr);
+
+    if opt.sweep_flags {
+        write_sweep(&mut wtr, &opt, &program)?;
+    } else {
+        let flags = Flags {
+            shot: opt.script.shot_with_rank,
+            accel_shot: opt.script.accel_shot_with_rank,
+            homing_shot: opt.script.homing_shot_with_rank,
+            extra_act: opt.script.extra_act_with_rank,
+            accel: opt.script.accel_with_rank,
+        };
+        let matrix = run_matrix(&opt, &program, flags)?;
+        if opt.csv {
+            write_csv(&mut wtr, &matrix)?;
+        } else {
+            write_table(&mut wtr, &matrix)?;
+        }
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,403 @@
+//! スクリプトをヘッドレスで実行し、フレームごとの状態を CSV/JSON で出力する。
+
+use std::io::Write;
+
+use structopt::StructOpt;
+
+use starsoldier_bytecode as bytecode;
+
+mod common;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    #[structopt(flatten)]
+    script: common::ScriptOpt,
+
+    #[structopt(flatten)]
+    hero: common::HeroOpt,
+
+    #[structopt(long, default_value = "0")]
+    rank: u8,
+
+    /// このフレーム数ごとにランクを 1 上げる (最大 7)
+    #[structopt(long)]
+    rank_rise_interval: Option<usize>,
+
+    /// 面 (1..=16)
+    #[structopt(long, default_value = "1")]
+    stage: u8,
+    #[structopt(long)]
+    second_round: bool,
+
+    /// 指定フレームの終わりに敵へダメージを与える ("30,45,60")。敵はスロット 0 のみ
+    #[structopt(long, default_value = "")]
+    damage_at: bytecode::DamageSchedule,
+
+    /// 乱数のシード
+    #[structopt(long, default_value = "1")]
+    seed: u16,
+
+    /// rand() の戻り値を記録したバイナリファイル (指定時は --seed を無視してこの列を再生する)
+    #[structopt(long, parse(from_os_str))]
+    rand_values: Option<std::path::PathBuf>,
+
+    /// 最大フレーム数 (敵が消滅したらそこで終了)
+    #[structopt(long, default_value = "1000")]
+    frames: usize,
+
+    /// y がこの値未満なら敵を画面外とみなす
+    #[structopt(long)]
+    clip_top: Option<u8>,
+
+    /// y がこの値以上なら敵を画面外とみなす (原作は 239)
+    #[structopt(long, default_value = "239")]
+    clip_bottom: u8,
+
+    /// 下端での画面外判定をしない (--clip-bottom を無視する)
+    #[structopt(long)]
+    no_clip_bottom: bool,
+
+    /// x がこの値未満なら敵を画面外とみなす
+    #[structopt(long)]
+    clip_left: Option<u8>,
+
+    /// x がこの値以上なら敵を画面外とみなす
+    #[structopt(long)]
+    clip_right: Option<u8>,
+
+    /// x 座標が範囲を越えたときの扱い (wrap, saturate, leave)
+    #[structopt(long, default_value = "wrap", possible_values = &["wrap", "saturate", "leave"])]
+    overflow_x: String,
+
+    /// y 座標が範囲を越えたときの扱い (wrap, saturate, leave)
+    #[structopt(long, default_value = "wrap", possible_values = &["wrap", "saturate", "leave"])]
+    overflow_y: String,
+
+    /// 敵弾もシミュレートし、出力に bullets 列を加える
+    #[structopt(long)]
+    bullets: bool,
+
+    /// 同時に存在できる敵弾の数
+    #[structopt(long, default_value = "8")]
+    max_bullets: usize,
+
+    /// 誘導弾が自機を追尾するフレーム数
+    #[structopt(long, default_value = "32")]
+    homing_frames: u8,
+
+    /// 出力形式 (csv, json)
+    #[structopt(short, long, default_value = "csv", possible_values = &["csv", "json"])]
+    format: String,
+
+    /// 出力先 (省略時は標準出力)
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+}
+
+/// --rand-values の有無で乱数生成器を切り替える。
+#[derive(Debug)]
+enum SimRng {
+    Seeded(bytecode::SeededRng),
+    Recorded(bytecode::RecordedRng),
+}
+
+impl bytecode::Rng for SimRng {
+    fn rand(&mut self) -> u8 {
+        match self {
+            Self::Seeded(rng) => rng.rand(),
+            Self::Recorded(rng) => rng.rand(),
+        }
+    }
+}
+
+fn event_to_csv(event: &bytecode::Event) -> String {
+    use bytecode::Event;
+
+    match *event {
+        Event::ShootAim {
+            x,
+            y,
+            speed_mask,
+            force_homing,
+        } => format!(
+            "shoot_aim({} {} {:#04X} {})",
+            x, y, speed_mask, force_homing
+        ),
+        Event::ShootDirection { x, y, dir } => {
+            format!("shoot_direction({} {} {:#04X})", x, y, dir.index())
+        }
+        Event::RestoreMusic => "restore_music".to_owned(),
+        Event::PlaySound(sound) => format!("play_sound({})", sound),
+        Event::DamageSound => "damage_sound".to_owned(),
+        Event::DestructionSound => "destruction_sound".to_owned(),
+        Event::Left => "left".to_owned(),
+        Event::Destroyed => "destroyed".to_owned(),
+    }
+}
+
+fn event_to_json(event: &bytecode::Event) -> String {
+    use bytecode::Event;
+
+    match *event {
+        Event::ShootAim {
+            x,
+            y,
+            speed_mask,
+            force_homing,
+        } => format!(
+            r#"{{"type":"shoot_aim","x":{},"y":{},"speed_mask":{},"force_homing":{}}}"#,
+            x, y, speed_mask, force_homing
+        ),
+        Event::ShootDirection { x, y, dir } => format!(
+            r#"{{"type":"shoot_direction","x":{},"y":{},"dir":{}}}"#,
+            x,
+            y,
+            dir.index()
+        ),
+        Event::RestoreMusic => r#"{"type":"restore_music"}"#.to_owned(),
+        Event::PlaySound(sound) => format!(r#"{{"type":"play_sound","sound":{}}}"#, sound),
+        Event::DamageSound => r#"{"type":"damage_sound"}"#.to_owned(),
+        Event::DestructionSound => r#"{"type":"destruction_sound"}"#.to_owned(),
+        Event::Left => r#"{"type":"left"}"#.to_owned(),
+        Event::Destroyed => r#"{"type":"destroyed"}"#.to_owned(),
+    }
+}
+
+#[derive(Debug)]
+struct Record {
+    frame: usize,
+    x: u8,
+    y: u8,
+    sprite: u8,
+    state: bytecode::EnemyState,
+    rank: u8,
+    hero: (u8, u8),
+    events: Vec<bytecode::Event>,
+    bullets: Option<Vec<bytecode::Bullet>>,
+}
+
+/// --overflow-x/--overflow-y の値を Overflow に変換する。
+fn parse_overflow(s: &str) -> bytecode::Overflow {
+    match s {
+        "wrap" => bytecode::Overflow::Wrap,
+        "saturate" => bytecode::Overflow::Saturate,
+        "leave" => bytecode::Overflow::Leave,
+        _ => unreachable!(),
+    }
+}
+
+fn main() -> eyre::Result<()> {
+    let opt = Opt::from_args();
+    // 敵は 1 体 (スロット 0) だけなので、他のスロットへの予定は受け付けない。
+    if let Some(slot) = opt.damage_at.slots().find(|&slot| slot != 0) {
+        eyre::bail!("--damage-at: slot {} does not exist (only slot 0)", slot);
+    }
+
+    let (program, _) = opt.script.load()?;
+    let mut interp = bytecode::InterpreterInit {
+        rank: opt.rank,
+        clip: bytecode::ClipConfig {
+            top: opt.clip_top,
+            bottom: if opt.no_clip_bottom {
+                None
+            } else {
+                Some(opt.clip_bottom)
+            },
+            left: opt.clip_left,
+            right: opt.clip_right,
+            overflow_x: parse_overflow(&opt.overflow_x),
+            overflow_y: parse_overflow(&opt.overflow_y),
+        },
+        ..opt.script.interpreter_init(program)
+    }
+    .init()?;
+
+    let rng = match &opt.rand_values {
+        Some(path) => SimRng::Recorded(bytecode::RecordedRng::new(std::fs::read(path)?)),
+        None => SimRng::Seeded(bytecode::SeededRng::new(opt.seed)),
+    };
+
+    let mut hero = opt.hero.make_hero()?;
+    let (hero_x, hero_y) = hero.position();
+
+    let mut input = bytecode::GameParts {
+        env: bytecode::FixedEnvironment {
+            stage: opt.stage,
+            second_round: opt.second_round,
+            hero_x,
+            hero_y,
+            rank: None,
+        },
+        rng,
+        shooter: (),
+        audio: (),
+    };
+
+    let mut rank_model = opt
+        .rank_rise_interval
+        .map(|interval| bytecode::RankModel::new(opt.rank, Some(interval), None, 0));
+
+    let mut bullets = opt.bullets.then(|| {
+        let mut bullets = bytecode::Bullets::new(bytecode::BulletConfig {
+            max_bullets: opt.max_bullets,
+            homing_frames: opt.homing_frames,
+            ..Default::default()
+        });
+        bullets.set_target(hero_x, hero_y);
+        bullets
+    });
+
+    let mut records = vec![Record {
+        frame: 0,
+        x: interp.x(),
+        y: interp.y(),
+        sprite: interp.sprite_index(),
+        state: interp.state(),
+        rank: interp.rank(),
+        hero: (hero_x, hero_y),
+        events: vec![],
+        bullets: bullets.as_ref().map(|b| b.bullets().to_vec()),
+    }];
+    let mut error = None;
+    for frame in 1..=opt.frames {
+        let (hero_x, hero_y) = hero.position();
+        input.env.hero_x = hero_x;
+        input.env.hero_y = hero_y;
+        input.env.rank = rank_model.as_ref().map(bytecode::RankModel::rank);
+
+        // 既存の弾を動かしてから、このフレームで撃たれた弾を追加する。
+        if let Some(bullets) = &mut bullets {
+            bullets.set_target(hero_x, hero_y);
+            bullets.step();
+        }
+        let mut events = vec![];
+        let mut res = interp.step_events(&mut input, &mut events);
+        let scheduled = opt.damage_at.hits(frame).contains(&0);
+        if res.is_ok() && scheduled && matches!(interp.state(), bytecode::EnemyState::Alive) {
+            res = interp.damage_events(&mut input, &mut events);
+        }
+        if let Err(e) = res {
+            error = Some(e);
+            break;
+        }
+        if let Some(bullets) = &mut bullets {
+            for event in &events {
+                bullets.shoot_event(event);
+            }
+        }
+        hero.step(bullets.as_ref().map_or(&[], |b| b.bullets()));
+        if let Some(rank_model) = &mut rank_model {
+            rank_model.on_frame();
+        }
+        records.push(Record {
+            frame,
+            x: interp.x(),
+            y: interp.y(),
+            sprite: interp.sprite_index(),
+            state: interp.state(),
+            rank: interp.rank(),
+            hero: (hero_x, hero_y),
+            events,
+            bullets: bullets.as_ref().map(|b| b.bullets().to_vec()),
+        });
+        if !matches!(interp.state(), bytecode::EnemyState::Alive) {
+            break;
+        }
+    }
+
+    let wtr: Box<dyn Write> = match &opt.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut wtr = std::io::BufWriter::new(wtr);
+
+    if opt.format == "json" {
+        write_json(&mut wtr, &records)?;
+    } else {
+        write_csv(&mut wtr, &records)?;
+    }
+    wtr.flush()?;
+
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+fn write_csv<W: Write>(mut wtr: W, records: &[Record]) -> std::io::Result<()> {
+    let with_bullets = matches!(records.first(), Some(rec) if rec.bullets.is_some());
+
+    write!(wtr, "frame,x,y,sprite,state,rank,hero_x,hero_y,events")?;
+    if with_bullets {
+        write!(wtr, ",bullets")?;
+    }
+    writeln!(wtr)?;
+    for rec in records {
+        let events: Vec<_> = rec.events.iter().map(event_to_csv).collect();
+        write!(
+            wtr,
+            "{},{},{},{},{:?},{},{},{},{}",
+            rec.frame,
+            rec.x,
+            rec.y,
+            rec.sprite,
+            rec.state,
+            rec.rank,
+            rec.hero.0,
+            rec.hero.1,
+            events.join("|")
+        )?;
+        if let Some(bullets) = &rec.bullets {
+            let bullets: Vec<_> = bullets.iter().map(|b| format!("{}:{}", b.x, b.y)).collect();
+            write!(wtr, ",{}", bullets.join("|"))?;
+        }
+        writeln!(wtr)?;
+    }
+
+    Ok(())
+}
+
+fn write_json<W: Write>(mut wtr: W, records: &[Record]) -> std::io::Result<()> {
+    writeln!(wtr, "[")?;
+    for (i, rec) in records.iter().enumerate() {
+        let events: Vec<_> = rec.events.iter().map(event_to_json).collect();
+        let bullets = match &rec.bullets {
+            Some(bullets) => {
+                let bullets: Vec<_> = bullets
+                    .iter()
+                    .map(|b| {
+                        format!(
+                            r#"{{"x":{},"y":{},"dir":{},"homing":{}}}"#,
+                            b.x,
+                            b.y,
+                            b.dir.index(),
+                            b.homing_timer > 0
+                        )
+                    })
+                    .collect();
+                format!(r#","bullets":[{}]"#, bullets.join(","))
+            }
+            None => String::new(),
+        };
+        let sep = if i + 1 < records.len() { "," } else { "" };
+        writeln!(
+            wtr,
+            r#"  {{"frame":{},"x":{},"y":{},"sprite":{},"state":"{:?}","rank":{},"hero_x":{},"hero_y":{},"events":[{}]{}}}{}"#,
+            rec.frame,
+            rec.x,
+            rec.y,
+            rec.sprite,
+            rec.state,
+            rec.rank,
+            rec.hero.0,
+            rec.hero.1,
+            events.join(","),
+            bullets,
+            sep
+        )?;
+    }
+    writeln!(wtr, "]")?;
+
+    Ok(())
+}
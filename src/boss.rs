@@ -0,0 +1,250 @@
+use thiserror::Error;
+
+use crate::clip::{ClipConfig, Overflow};
+use crate::game::*;
+use crate::interpret::*;
+
+#[derive(Debug, Error)]
+pub enum BossError {
+    #[error("core: {0}")]
+    Core(#[source] InterpretError),
+
+    #[error("part {part}: {source}")]
+    Part {
+        part: usize,
+        #[source]
+        source: InterpretError,
+    },
+
+    #[error("core: interpreter is not in boss mode")]
+    CoreNotBoss,
+
+    #[error("part {part}: interpreter is not in boss mode")]
+    PartNotBoss { part: usize },
+}
+
+pub type BossResult<T> = Result<T, BossError>;
+
+/// パーツの画面外判定の規則。パーツの移動は無視されるので、パーツ単独では画面外に出ない。
+const PART_CLIP: ClipConfig = ClipConfig {
+    top: None,
+    bottom: None,
+    left: None,
+    right: None,
+
+    overflow_x: Overflow::Wrap,
+    overflow_y: Overflow::Wrap,
+};
+
+/// ボスのパーツ。
+#[derive(Clone, Debug)]
+pub struct BossPart {
+    /// コアからの相対位置。
+    pub offset: (i8, i8),
+
+    /// パーツ独自のスクリプト (弾を撃つ砲台など)。None ならコアに追従するだけ。
+    pub interp: Option<Interpreter>,
+}
+
+/// 複数パーツからなるボス。
+///
+/// コアのスクリプトがボス全体の移動と HP を担い、各パーツはコアから一定の相対位置に置かれる。
+/// パーツのスクリプトは毎フレームコアの後に実行されるが、その移動は無視される
+/// (実行前後にパーツの位置をコア + offset に戻す)。パーツの画面外判定も行わない。
+/// コアが生存状態でなくなったフレームでは、パーツのスクリプトは実行しない。
+#[derive(Clone, Debug)]
+pub struct Boss {
+    core: Interpreter,
+    parts: Vec<BossPart>,
+}
+
+impl Boss {
+    /// コアと全パーツのインタプリタはボスモードでなければならない。
+    /// パーツのインタプリタの ClipConfig は画面外判定をしないものに置き換える。
+    pub fn new(core: Interpreter, mut parts: Vec<BossPart>) -> BossResult<Self> {
+        if !core.is_boss() {
+            return Err(BossError::CoreNotBoss);
+        }
+        for (i, part) in parts.iter().enumerate() {
+            if matches!(&part.interp, Some(interp) if !interp.is_boss()) {
+                return Err(BossError::PartNotBoss { part: i });
+            }
+        }
+
+        for interp in parts.iter_mut().filter_map(|part| part.interp.as_mut()) {
+            interp.set_clip(PART_CLIP);
+        }
+
+        let mut boss = Self { core, parts };
+        boss.place_parts();
+
+        Ok(boss)
+    }
+
+    /// 1 フレーム進める。コア、パーツの順に実行する。
+    ///
+    /// 生存状態でないパーツのスクリプトは実行しない。
+    pub fn step<G: Game>(&mut self, game: &mut G) -> BossResult<()> {
+        self.core.step(game).map_err(BossError::Core)?;
+
+        self.place_parts();
+        if !matches!(self.core.state(), EnemyState::Alive) {
+            self.sync_part_states();
+            return Ok(());
+        }
+
+        for (i, part) in self.parts.iter_mut().enumerate() {
+            if let Some(interp) = &mut part.interp {
+                if matches!(interp.state(), EnemyState::Alive) {
+                    interp
+                        .step(game)
+                        .map_err(|source| BossError::Part { part: i, source })?;
+                }
+            }
+        }
+        self.place_parts();
+
+        Ok(())
+    }
+
+    /// 被弾処理。どのパーツに当たっても共有の HP (コアの HP) が減る。
+    pub fn damage<G: Game>(&mut self, game: &mut G) -> BossResult<()> {
+        self.core.damage(game).map_err(BossError::Core)?;
+
+        self.sync_part_states();
+
+        Ok(())
+    }
+
+    /// コアが消滅したら全パーツも同じ状態にする。
+    fn sync_part_states(&mut self) {
+        let state = self.core.state();
+        if matches!(state, EnemyState::Alive) {
+            return;
+        }
+
+        for interp in self
+            .parts
+            .iter_mut()
+            .filter_map(|part| part.interp.as_mut())
+        {
+            interp.set_state(state);
+        }
+    }
+
+    /// パーツの位置をコア + offset に合わせる。
+    fn place_parts(&mut self) {
+        let (x, y) = (self.core.x(), self.core.y());
+        for part in &mut self.parts {
+            if let Some(interp) = &mut part.interp {
+                let (px, py) = Self::offset_position((x, y), part.offset);
+                interp.set_position(px, py);
+            }
+        }
+    }
+
+    fn offset_position((x, y): (u8, u8), (dx, dy): (i8, i8)) -> (u8, u8) {
+        (x.wrapping_add(dx as u8), y.wrapping_add(dy as u8))
+    }
+
+    pub fn core(&self) -> &Interpreter {
+        &self.core
+    }
+
+    pub fn parts(&self) -> &[BossPart] {
+        &self.parts
+    }
+
+    pub fn state(&self) -> EnemyState {
+        self.core.state()
+    }
+
+    pub fn position(&self) -> (u8, u8) {
+        (self.core.x(), self.core.y())
+    }
+
+    /// 共有の HP。
+    pub fn health(&self) -> u8 {
+        self.core.health()
+    }
+
+    /// パーツ i の位置。
+    pub fn part_position(&self, i: usize) -> Option<(u8, u8)> {
+        let part = self.parts.get(i)?;
+        Some(Self::offset_position(self.position(), part.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::testutil::*;
+
+    const SHOOTER: &str = "L:\nshoot_direction 4\nmove 0x08\njump L\n";
+
+    fn boss_interpreter(src: &str, x: u8, y: u8) -> Interpreter {
+        InterpreterInit {
+            boss: true,
+            x,
+            y,
+            ..interpreter_init(src)
+        }
+        .init()
+        .unwrap()
+    }
+
+    fn boss(core_src: &str, core_y: u8, offset: (i8, i8)) -> Boss {
+        Boss::new(
+            boss_interpreter(core_src, 100, core_y),
+            vec![BossPart {
+                offset,
+                interp: Some(boss_interpreter(SHOOTER, 0, 0)),
+            }],
+        )
+        .unwrap()
+    }
+
+    /// 1 フレーム実行し、発射された弾の数を返す。
+    fn step_shots(boss: &mut Boss) -> usize {
+        let mut game = game(Vec::<Event>::new());
+        boss.step(&mut game).unwrap();
+        game.shooter.len()
+    }
+
+    #[test]
+    fn parts_do_not_run_after_core_leaves() {
+        let mut boss = boss("L:\nmove 0x08\njump L\n", 200, (0, -40));
+
+        let mut frames = 0;
+        while matches!(boss.core().state(), EnemyState::Alive) {
+            let shots = step_shots(&mut boss);
+            frames += 1;
+            assert!(frames < 100);
+            if matches!(boss.core().state(), EnemyState::Alive) {
+                assert_eq!(shots, 1);
+            } else {
+                assert_eq!(shots, 0);
+            }
+        }
+
+        assert!(matches!(
+            boss.parts()[0].interp.as_ref().unwrap().state(),
+            EnemyState::Leaving
+        ));
+    }
+
+    #[test]
+    fn parts_are_not_clipped() {
+        // パーツは下端の境界上にあり、単独で動けば画面外に出る。
+        let mut boss = boss("L:\nmove 0x04\njump L\n", 200, (0, 38));
+
+        for _ in 0..10 {
+            assert_eq!(step_shots(&mut boss), 1);
+            assert!(matches!(
+                boss.parts()[0].interp.as_ref().unwrap().state(),
+                EnemyState::Alive
+            ));
+        }
+    }
+}
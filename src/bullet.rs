@@ -0,0 +1,202 @@
+use crate::clip::{ClipConfig, Overflow};
+use crate::direction::Direction;
+use crate::event::Event;
+use crate::game::Shooter;
+
+/// 敵弾。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bullet {
+    pub x: u8,
+    pub y: u8,
+
+    /// 速度指定込みの方向 (0..=0x3F)。
+    pub dir: Direction,
+
+    /// 残り誘導フレーム数。0 なら直進する。
+    pub homing_timer: u8,
+}
+
+impl Bullet {
+    /// 1 フレーム分移動する。画面外に出たら false を返す。
+    ///
+    /// 誘導中なら移動前に (target_x, target_y) へ向けて方向を取り直す (速度指定は維持する)。
+    fn step(&mut self, (target_x, target_y): (u8, u8), clip: &ClipConfig) -> bool {
+        if self.homing_timer > 0 {
+            self.homing_timer -= 1;
+            let aim = Direction::aim((self.x, self.y), (target_x, target_y));
+            self.dir = Direction::new(aim.index() | (self.dir.index() & 0x30));
+        }
+
+        match clip.displace((self.x, self.y), self.dir.displacement_bullet()) {
+            Some((x, y)) if !clip.is_outside((x, y)) => {
+                self.x = x;
+                self.y = y;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 敵弾システムの設定。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BulletConfig {
+    /// 同時に存在できる弾の数。空きスロットがなければ発射要求は無視される。
+    pub max_bullets: usize,
+
+    /// 誘導弾が自機を追尾するフレーム数。
+    pub homing_frames: u8,
+
+    /// 弾の画面外判定。既定値は敵と同じ規則 (ClipConfig::ROM) で、左右のみ折り返さずに消滅させる。
+    pub clip: ClipConfig,
+}
+
+impl Default for BulletConfig {
+    fn default() -> Self {
+        Self {
+            max_bullets: 8,
+            homing_frames: 32,
+            clip: ClipConfig {
+                overflow_x: Overflow::Leave,
+                ..ClipConfig::ROM
+            },
+        }
+    }
+}
+
+/// 敵弾の集合。原作の弾処理の近似である。
+///
+/// Shooter を実装しているので、GameParts の shooter としてそのまま使える。
+/// 自機狙い弾の狙う位置は set_target() で設定する (通常は毎フレーム自機の位置を渡す)。
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bullets {
+    config: BulletConfig,
+    bullets: Vec<Bullet>,
+    target: (u8, u8),
+}
+
+impl Bullets {
+    pub fn new(config: BulletConfig) -> Self {
+        Self {
+            config,
+            bullets: Vec::with_capacity(config.max_bullets),
+            target: (0, 0),
+        }
+    }
+
+    pub fn config(&self) -> &BulletConfig {
+        &self.config
+    }
+
+    pub fn bullets(&self) -> &[Bullet] {
+        &self.bullets
+    }
+
+    pub fn len(&self) -> usize {
+        self.bullets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bullets.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.bullets.len() >= self.config.max_bullets
+    }
+
+    pub fn clear(&mut self) {
+        self.bullets.clear();
+    }
+
+    pub fn target(&self) -> (u8, u8) {
+        self.target
+    }
+
+    /// 自機狙い弾と誘導弾が狙う位置を設定する。
+    pub fn set_target(&mut self, x: u8, y: u8) {
+        self.target = (x, y);
+    }
+
+    /// 弾を追加する。空きスロットがなければ false を返す。
+    pub fn spawn(&mut self, bullet: Bullet) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.bullets.push(bullet);
+        true
+    }
+
+    /// 発射イベントなら弾を追加する。弾が追加されたかどうかを返す。
+    pub fn shoot_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::ShootAim {
+                x,
+                y,
+                speed_mask,
+                force_homing,
+            } => self.shoot_aim(x, y, speed_mask, force_homing),
+            Event::ShootDirection { x, y, dir } => self.shoot_direction(x, y, dir),
+            _ => false,
+        }
+    }
+
+    /// (x, y) から狙う位置へ向けて弾を撃つ。弾が追加されたかどうかを返す。
+    pub fn shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) -> bool {
+        let aim = Direction::aim((x, y), self.target);
+        self.spawn(Bullet {
+            x,
+            y,
+            dir: Direction::new(aim.index() | (speed_mask & 0x30)),
+            homing_timer: if force_homing {
+                self.config.homing_frames
+            } else {
+                0
+            },
+        })
+    }
+
+    /// (x, y) から方向 dir (速度指定込み) へ弾を撃つ。弾が追加されたかどうかを返す。
+    pub fn shoot_direction(&mut self, x: u8, y: u8, dir: Direction) -> bool {
+        self.spawn(Bullet {
+            x,
+            y,
+            dir,
+            homing_timer: 0,
+        })
+    }
+
+    /// f が false を返した弾を取り除く。
+    pub fn retain<F: FnMut(&Bullet) -> bool>(&mut self, f: F) {
+        self.bullets.retain(f);
+    }
+
+    /// 全ての弾を 1 フレーム分移動し、画面外に出たものを取り除く。
+    pub fn step(&mut self) {
+        let target = self.target;
+        let clip = self.config.clip;
+        let bullets = std::mem::take(&mut self.bullets);
+        self.bullets = bullets
+            .into_iter()
+            .filter_map(|mut bullet| {
+                if bullet.step(target, &clip) {
+                    Some(bullet)
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+}
+
+impl Shooter for Bullets {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        self.shoot_aim(x, y, speed_mask, force_homing);
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction) {
+        self.shoot_direction(x, y, dir);
+    }
+}
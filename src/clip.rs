@@ -0,0 +1,160 @@
+/// 移動で座標が u8 の範囲を越えたときの扱い。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Overflow {
+    /// 反対側へ折り返す (原作の挙動)。
+    Wrap,
+    /// 0 または 255 で止める。
+    Saturate,
+    /// 画面外に出たとみなして消滅させる。
+    Leave,
+}
+
+/// 敵の画面外判定の規則。
+///
+/// 移動のたびに、まず各軸の Overflow に従って座標を更新し、その後 top/bottom/left/right で判定する。
+/// 既定値 (ClipConfig::ROM) は原作と同じく、両軸とも折り返し、y >= 239 のみを画面外とする。
+/// 上端から出た場合も y が折り返して 239 以上になるので、下端の判定で消滅する。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClipConfig {
+    /// y がこの値未満なら画面外。
+    pub top: Option<u8>,
+    /// y がこの値以上なら画面外。
+    pub bottom: Option<u8>,
+    /// x がこの値未満なら画面外。
+    pub left: Option<u8>,
+    /// x がこの値以上なら画面外。
+    pub right: Option<u8>,
+
+    pub overflow_x: Overflow,
+    pub overflow_y: Overflow,
+}
+
+impl ClipConfig {
+    pub const ROM: Self = Self {
+        top: None,
+        bottom: Some(239),
+        left: None,
+        right: None,
+
+        overflow_x: Overflow::Wrap,
+        overflow_y: Overflow::Wrap,
+    };
+
+    /// (x, y) を (dx, dy) だけ動かした座標を返す。
+    /// Overflow::Leave の軸で範囲を越えた場合は None を返す。
+    pub fn displace(&self, (x, y): (u8, u8), (dx, dy): (i8, i8)) -> Option<(u8, u8)> {
+        let x = Self::displace_axis(x, dx, self.overflow_x)?;
+        let y = Self::displace_axis(y, dy, self.overflow_y)?;
+        Some((x, y))
+    }
+
+    fn displace_axis(pos: u8, d: i8, overflow: Overflow) -> Option<u8> {
+        match overflow {
+            Overflow::Wrap => Some(pos.wrapping_add(d as u8)),
+            Overflow::Saturate => Some(if d < 0 {
+                pos.saturating_sub(d.unsigned_abs())
+            } else {
+                pos.saturating_add(d as u8)
+            }),
+            Overflow::Leave => {
+                if d < 0 {
+                    pos.checked_sub(d.unsigned_abs())
+                } else {
+                    pos.checked_add(d as u8)
+                }
+            }
+        }
+    }
+
+    /// (x, y) が画面外かどうかを返す。
+    pub fn is_outside(&self, (x, y): (u8, u8)) -> bool {
+        matches!(self.top, Some(top) if y < top)
+            || matches!(self.bottom, Some(bottom) if y >= bottom)
+            || matches!(self.left, Some(left) if x < left)
+            || matches!(self.right, Some(right) if x >= right)
+    }
+}
+
+impl Default for ClipConfig {
+    fn default() -> Self {
+        Self::ROM
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_matches_original_rule() {
+        // 原作: 座標は折り返し、y >= 239 で画面外。
+        for x in 0..=u8::MAX {
+            for y in 0..=u8::MAX {
+                assert_eq!(
+                    ClipConfig::ROM.is_outside((x, y)),
+                    y >= 239,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+        for &d in &[-128, -3, 0, 3, 127] {
+            for pos in 0..=u8::MAX {
+                assert_eq!(
+                    ClipConfig::ROM.displace((pos, pos), (d, d)),
+                    Some((pos.wrapping_add(d as u8), pos.wrapping_add(d as u8)))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn displace_saturate() {
+        let clip = ClipConfig {
+            overflow_x: Overflow::Saturate,
+            overflow_y: Overflow::Saturate,
+            ..ClipConfig::ROM
+        };
+        assert_eq!(clip.displace((2, 253), (-3, 3)), Some((0, 255)));
+        assert_eq!(clip.displace((2, 253), (-128, 127)), Some((0, 255)));
+        assert_eq!(clip.displace((10, 10), (-3, 3)), Some((7, 13)));
+    }
+
+    #[test]
+    fn displace_leave() {
+        let clip = ClipConfig {
+            overflow_x: Overflow::Leave,
+            ..ClipConfig::ROM
+        };
+        assert_eq!(clip.displace((2, 0), (-3, -3)), None);
+        assert_eq!(clip.displace((253, 0), (3, 0)), None);
+        assert_eq!(clip.displace((3, 0), (-3, -3)), Some((0, 253)));
+        assert_eq!(clip.displace((252, 0), (3, 0)), Some((255, 0)));
+    }
+
+    #[test]
+    fn is_outside_edges() {
+        let clip = ClipConfig {
+            top: Some(16),
+            bottom: Some(224),
+            left: Some(8),
+            right: Some(248),
+            ..ClipConfig::ROM
+        };
+        assert!(!clip.is_outside((8, 16)));
+        assert!(!clip.is_outside((247, 223)));
+        assert!(clip.is_outside((100, 15)));
+        assert!(clip.is_outside((100, 224)));
+        assert!(clip.is_outside((7, 100)));
+        assert!(clip.is_outside((248, 100)));
+
+        let clip = ClipConfig {
+            bottom: None,
+            ..ClipConfig::ROM
+        };
+        assert!(!clip.is_outside((0, 255)));
+    }
+}
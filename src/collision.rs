@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+/// 中心からの半幅・半高さで表す当たり判定。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hitbox {
+    pub half_width: u8,
+    pub half_height: u8,
+}
+
+impl Hitbox {
+    pub const fn new(half_width: u8, half_height: u8) -> Self {
+        Self {
+            half_width,
+            half_height,
+        }
+    }
+
+    /// pos にある self と other_pos にある other が重なっているかどうかを返す。
+    pub fn overlaps(self, (x, y): (u8, u8), other: Self, (ox, oy): (u8, u8)) -> bool {
+        fn abs_diff(a: u8, b: u8) -> u8 {
+            if a >= b {
+                a - b
+            } else {
+                b - a
+            }
+        }
+
+        let reach_x = u16::from(self.half_width) + u16::from(other.half_width);
+        let reach_y = u16::from(self.half_height) + u16::from(other.half_height);
+
+        u16::from(abs_diff(x, ox)) <= reach_x && u16::from(abs_diff(y, oy)) <= reach_y
+    }
+}
+
+/// World の当たり判定の設定。
+///
+/// 自機は shot_interval フレームごとに真上へ自機弾を撃ち、自機弾が敵に当たると damage() が呼ばれる。
+/// 自機と敵・敵弾の接触は HeroHit として記録するだけで、残機などは扱わない。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CollisionConfig {
+    pub hero: Hitbox,
+    pub enemy: Hitbox,
+    pub bullet: Hitbox,
+    pub shot: Hitbox, // 自機弾
+
+    /// 自機弾の発射間隔 (フレーム)。0 なら撃たない。
+    pub shot_interval: u8,
+    /// 自機弾の 1 フレームの移動量。
+    pub shot_speed: u8,
+    /// 同時に存在できる自機弾の数。
+    pub max_shots: usize,
+}
+
+impl Default for CollisionConfig {
+    fn default() -> Self {
+        Self {
+            hero: Hitbox::new(4, 4),
+            enemy: Hitbox::new(8, 8),
+            bullet: Hitbox::new(2, 2),
+            shot: Hitbox::new(2, 4),
+
+            shot_interval: 8,
+            shot_speed: 8,
+            max_shots: 3,
+        }
+    }
+}
+
+/// 自機が敵・敵弾に接触したことを表す。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeroHit {
+    Enemy { slot: usize },
+    Bullet { x: u8, y: u8 },
+}
+
+#[derive(Debug, Error)]
+#[error("invalid damage schedule (expected \"frame[:slot],...\"): {0}")]
+pub struct ParseDamageScheduleError(String);
+
+/// 指定フレームに指定スロットの敵へダメージを与える予定表。
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DamageSchedule {
+    hits: BTreeMap<usize, Vec<usize>>,
+}
+
+impl DamageSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// フレーム frame の終わりにスロット slot の敵へダメージを与える予定を追加する。
+    pub fn add(&mut self, frame: usize, slot: usize) {
+        self.hits.entry(frame).or_default().push(slot);
+    }
+
+    /// フレーム frame にダメージを与えるスロットの列を返す。
+    pub fn hits(&self, frame: usize) -> &[usize] {
+        self.hits.get(&frame).map_or(&[], Vec::as_slice)
+    }
+
+    /// 予定表に現れるスロットを返す (重複を含む)。
+    pub fn slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.hits.values().flatten().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+}
+
+impl std::str::FromStr for DamageSchedule {
+    type Err = ParseDamageScheduleError;
+
+    /// "30,45:1,60" のような形式。スロットを省略すると 0 とみなす。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDamageScheduleError(s.to_owned());
+
+        let mut schedule = Self::new();
+        for tok in s.split(',').map(str::trim).filter(|tok| !tok.is_empty()) {
+            let (frame, slot) = tok.split_once(':').unwrap_or((tok, "0"));
+            let frame = frame.trim().parse().map_err(|_| err())?;
+            let slot = slot.trim().parse().map_err(|_| err())?;
+            schedule.add(frame, slot);
+        }
+
+        Ok(schedule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hitbox_overlaps() {
+        let a = Hitbox::new(4, 2);
+        let b = Hitbox::new(2, 1);
+
+        assert!(a.overlaps((100, 100), b, (106, 103)));
+        assert!(a.overlaps((106, 103), b, (100, 100)));
+        assert!(!a.overlaps((100, 100), b, (107, 100)));
+        assert!(!a.overlaps((100, 100), b, (100, 104)));
+
+        // 座標は折り返さない。
+        assert!(!a.overlaps((0, 0), b, (255, 0)));
+        assert!(Hitbox::new(255, 255).overlaps((0, 0), Hitbox::new(0, 0), (255, 255)));
+    }
+
+    #[test]
+    fn damage_schedule_from_str() {
+        let schedule: DamageSchedule = " 30, 45:1 ,45:2,60".parse().unwrap();
+        assert_eq!(schedule.hits(30), [0]);
+        assert_eq!(schedule.hits(45), [1, 2]);
+        assert_eq!(schedule.hits(60), [0]);
+        assert!(schedule.hits(31).is_empty());
+        assert_eq!(schedule.slots().collect::<Vec<_>>(), [0, 1, 2, 0]);
+
+        assert!("".parse::<DamageSchedule>().unwrap().is_empty());
+        for s in &["x", "30:", "30:a", "-1", "30:1:2"] {
+            assert!(s.parse::<DamageSchedule>().is_err(), "{:?}", s);
+        }
+    }
+}
@@ -23,16 +23,41 @@ pub enum DisasmError {
 
 pub type DisasmResult<T> = Result<T, DisasmError>;
 
-pub fn disasm<W: Write>(mut wtr: W, buf: &[u8]) -> DisasmResult<()> {
+/// 逆アセンブル結果に付加するラベル定義・コメント。
+#[derive(Debug, Default)]
+pub(crate) struct Annotations {
+    /// アドレスごとの前置行を出現順に保持する。
+    pub(crate) leading: HashMap<usize, Vec<Leading>>,
+
+    /// アドレスごとの行末コメント。
+    pub(crate) trailing: HashMap<usize, Vec<String>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Leading {
+    Label(String),
+    Comment(String),
+}
+
+pub fn disasm<W: Write>(wtr: W, buf: &[u8]) -> DisasmResult<()> {
+    disasm_annotated(wtr, buf, &Annotations::default())
+}
+
+pub(crate) fn disasm_annotated<W: Write>(
+    mut wtr: W,
+    buf: &[u8],
+    annotations: &Annotations,
+) -> DisasmResult<()> {
     #[derive(Debug)]
     struct Statement {
         addr: usize,
         op: Op,
+        scope: Option<String>, // 直前のグローバルラベル
     }
 
     let mut stmts = vec![];
     let mut addrs_opcode = HashSet::new();
-    let mut addr_to_label = HashMap::new();
+    let mut addrs_destination = HashSet::new();
 
     let mut addr = 0;
     while !buf[addr..].is_empty() {
@@ -48,7 +73,7 @@ pub fn disasm<W: Write>(mut wtr: W, buf: &[u8]) -> DisasmResult<()> {
         // UnsetJumpOnDamage も実際は SetHealth の可能性があるが、ここでは判別できないのでそのままにする。
         if let Some(addr_dst) = op.addr_destination() {
             if (0..buf.len()).contains(&usize::from(addr_dst)) {
-                addr_to_label.insert(usize::from(addr_dst), format!("L{:02X}", addr_dst));
+                addrs_destination.insert(usize::from(addr_dst));
             } else {
                 if matches!(op, Op::SetJumpOnDamage(_)) {
                     op = Op::SetHealth(addr_dst);
@@ -59,85 +84,210 @@ pub fn disasm<W: Write>(mut wtr: W, buf: &[u8]) -> DisasmResult<()> {
         }
 
         addrs_opcode.insert(addr);
-        stmts.push(Statement { addr, op });
+        stmts.push(Statement {
+            addr,
+            op,
+            scope: None,
+        });
         addr += op.len();
     }
 
+    // 各命令の前に書かれるラベルを順に辿り、命令ごとのスコープと参照に使うラベルを決める。
+    // 与えられたラベルは自動生成ラベルより優先し、参照にはスコープによらず使えるグローバルラベルを優先する。
+    // 自動生成ラベルはスコープ内ではローカルラベルにして、与えられたローカルラベルのスコープを壊さない。
+    let mut addr_to_label = HashMap::new();
+    let mut scope = None;
+    for stmt in &mut stmts {
+        let labels: Vec<_> = annotations
+            .leading
+            .get(&stmt.addr)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| match item {
+                Leading::Label(label) => Some(label),
+                Leading::Comment(_) => None,
+            })
+            .collect();
+        if let Some(label) = labels.iter().rev().find(|label| !label.starts_with('.')) {
+            scope = Some((*label).clone());
+        }
+
+        let label = labels
+            .iter()
+            .find(|label| !label.starts_with('.'))
+            .or_else(|| labels.first())
+            .map(|label| (*label).clone());
+        let label = label.or_else(|| {
+            if !addrs_destination.contains(&stmt.addr) {
+                None
+            } else if scope.is_some() {
+                Some(format!(".L{:02X}", stmt.addr))
+            } else {
+                let label = format!("L{:02X}", stmt.addr);
+                scope = Some(label.clone());
+                Some(label)
+            }
+        });
+        if let Some(name) = label {
+            let label_scope = if name.starts_with('.') {
+                scope.clone()
+            } else {
+                None
+            };
+            addr_to_label.insert(
+                stmt.addr,
+                Label {
+                    scope: label_scope,
+                    name,
+                },
+            );
+        }
+
+        stmt.scope = scope.clone();
+    }
+
+    // 命令の途中を指す飛び先。定義は書かれないので、再アセンブルすると未定義ラベルになる。
+    for addr in addrs_destination {
+        addr_to_label.entry(addr).or_insert_with(|| Label {
+            scope: None,
+            name: format!("L{:02X}", addr),
+        });
+    }
+
     for stmt in stmts {
-        if let Some(label) = addr_to_label.get(&stmt.addr) {
-            writeln!(wtr, "{}:", label)?;
+        let mut label_written = false;
+        for addr in stmt.addr..stmt.addr + stmt.op.len() {
+            for item in annotations.leading.get(&addr).into_iter().flatten() {
+                match item {
+                    Leading::Label(label) if addr == stmt.addr => {
+                        writeln!(wtr, "{}:", label)?;
+                        label_written |= addr_to_label.get(&addr).map(|l| &l.name) == Some(label);
+                    }
+                    Leading::Label(label) => {
+                        writeln!(wtr, "; {}: (inside instruction at {:#04X})", label, addr)?;
+                    }
+                    Leading::Comment(comment) => writeln!(wtr, ";{}", comment)?,
+                }
+            }
+        }
+        if !label_written {
+            if let Some(label) = addr_to_label.get(&stmt.addr) {
+                writeln!(wtr, "{}:", label.name)?;
+            }
         }
 
+        let trailing: Vec<_> = (stmt.addr..stmt.addr + stmt.op.len())
+            .filter_map(|addr| annotations.trailing.get(&addr))
+            .flatten()
+            .collect();
+
         // TODO: ループも含めたインデント管理
         write!(wtr, "        ")?;
-
-        match stmt.op {
-            Op::Move(dir) => writeln!(wtr, "move {:#04X}", dir.index())?,
-            Op::Jump(addr) => writeln!(
-                wtr,
-                "jump {}",
-                addr_to_label.get(&usize::from(addr)).unwrap()
-            )?,
-            Op::SetSleepTimer(idx) => writeln!(wtr, "set_sleep_timer {}", idx)?,
-            Op::LoopBegin(idx) => writeln!(wtr, "loop_begin {}", idx)?,
-            Op::LoopEnd => writeln!(wtr, "loop_end")?,
-            Op::ShootDirection(dir) => writeln!(wtr, "shoot_direction {:#04X}", dir.index())?,
-            Op::SetSprite(idx) => writeln!(wtr, "set_sprite {}", idx)?,
-            Op::SetHomingTimer(idx) => writeln!(wtr, "set_homing_timer {}", idx)?,
-            Op::SetInversion(inv_x, inv_y) => writeln!(
-                wtr,
-                "set_inversion {}, {}",
-                u8::from(inv_x),
-                u8::from(inv_y)
-            )?,
-            Op::SetPosition(x, y) => writeln!(wtr, "set_position {}, {}", x, y)?,
-
-            // SetJumpOnDamage の場合、実際は SetHealth である可能性がある。
-            // オペランドのアドレスが命令境界でない場合、SetHealth とみなす。
-            Op::SetJumpOnDamage(addr) => {
-                if addrs_opcode.contains(&usize::from(addr)) {
-                    writeln!(
-                        wtr,
-                        "set_jump_on_damage {}",
-                        addr_to_label.get(&usize::from(addr)).unwrap()
-                    )?;
-                } else {
-                    writeln!(wtr, "set_health {}", addr)?;
-                }
+        if !trailing.is_empty() {
+            let mut line = Vec::<u8>::new();
+            write_op(
+                &mut line,
+                stmt.op,
+                stmt.scope.as_deref(),
+                &addrs_opcode,
+                &addr_to_label,
+            )?;
+            let line = String::from_utf8(line).unwrap();
+            write!(wtr, "{:<24}", line.trim_end())?;
+            for comment in trailing {
+                write!(wtr, " ;{}", comment)?;
             }
+            writeln!(wtr)?;
+        } else {
+            write_op(
+                &mut wtr,
+                stmt.op,
+                stmt.scope.as_deref(),
+                &addrs_opcode,
+                &addr_to_label,
+            )?;
+        }
+    }
 
-            Op::UnsetJumpOnDamage => writeln!(wtr, "unset_jump_on_damage")?,
-            Op::SetHealth(health) => writeln!(wtr, "set_health {}", health)?,
-            Op::IncrementSprite => writeln!(wtr, "increment_sprite")?,
-            Op::DecrementSprite => writeln!(wtr, "decrement_sprite")?,
-            Op::SetPart(part) => writeln!(wtr, "set_part {}", part)?,
-            Op::RandomizeX(mask) => writeln!(wtr, "randomize_x {:#04X}", mask)?,
-            Op::RandomizeY(mask) => writeln!(wtr, "randomize_y {:#04X}", mask)?,
-            Op::BccX(addr) => writeln!(
-                wtr,
-                "bcc_x {}",
-                addr_to_label.get(&usize::from(addr)).unwrap()
-            )?,
-            Op::BcsX(addr) => writeln!(
-                wtr,
-                "bcs_x {}",
-                addr_to_label.get(&usize::from(addr)).unwrap()
-            )?,
-            Op::BccY(addr) => writeln!(
-                wtr,
-                "bcc_y {}",
-                addr_to_label.get(&usize::from(addr)).unwrap()
-            )?,
-            Op::BcsY(addr) => writeln!(
-                wtr,
-                "bcs_y {}",
-                addr_to_label.get(&usize::from(addr)).unwrap()
-            )?,
-            Op::ShootAim(unused) => writeln!(wtr, "shoot_aim {}", unused)?,
-            Op::RestoreMusic => writeln!(wtr, "restore_music")?,
-            Op::PlaySound(sound) => writeln!(wtr, "play_sound {}", sound)?,
+    // 末尾以降の注釈 (コードの後ろのコメントなど)。
+    for item in annotations.leading.get(&buf.len()).into_iter().flatten() {
+        match item {
+            Leading::Label(label) => writeln!(wtr, "; {}:", label)?,
+            Leading::Comment(comment) => writeln!(wtr, ";{}", comment)?,
         }
     }
 
     Ok(())
 }
+
+/// 参照先のラベル。
+#[derive(Debug)]
+struct Label {
+    scope: Option<String>, // ローカルラベルならそのスコープ
+    name: String,
+}
+
+impl Label {
+    /// スコープ scope の中から参照するときの表記。他のスコープのローカルラベルは修飾する。
+    fn reference(&self, scope: Option<&str>) -> String {
+        match &self.scope {
+            Some(own) if Some(own.as_str()) != scope => format!("{}{}", own, self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+fn write_op<W: Write>(
+    mut wtr: W,
+    op: Op,
+    scope: Option<&str>,
+    addrs_opcode: &HashSet<usize>,
+    addr_to_label: &HashMap<usize, Label>,
+) -> DisasmResult<()> {
+    let label = |addr: u8| addr_to_label[&usize::from(addr)].reference(scope);
+
+    match op {
+        Op::Move(dir) => writeln!(wtr, "move {:#04X}", dir.index())?,
+        Op::Jump(addr) => writeln!(wtr, "jump {}", label(addr))?,
+        Op::SetSleepTimer(idx) => writeln!(wtr, "set_sleep_timer {}", idx)?,
+        Op::LoopBegin(idx) => writeln!(wtr, "loop_begin {}", idx)?,
+        Op::LoopEnd => writeln!(wtr, "loop_end")?,
+        Op::ShootDirection(dir) => writeln!(wtr, "shoot_direction {:#04X}", dir.index())?,
+        Op::SetSprite(idx) => writeln!(wtr, "set_sprite {}", idx)?,
+        Op::SetHomingTimer(idx) => writeln!(wtr, "set_homing_timer {}", idx)?,
+        Op::SetInversion(inv_x, inv_y) => writeln!(
+            wtr,
+            "set_inversion {}, {}",
+            u8::from(inv_x),
+            u8::from(inv_y)
+        )?,
+        Op::SetPosition(x, y) => writeln!(wtr, "set_position {}, {}", x, y)?,
+
+        // SetJumpOnDamage の場合、実際は SetHealth である可能性がある。
+        // オペランドのアドレスが命令境界でない場合、SetHealth とみなす。
+        Op::SetJumpOnDamage(addr) => {
+            if addrs_opcode.contains(&usize::from(addr)) {
+                writeln!(wtr, "set_jump_on_damage {}", label(addr))?;
+            } else {
+                writeln!(wtr, "set_health {}", addr)?;
+            }
+        }
+
+        Op::UnsetJumpOnDamage => writeln!(wtr, "unset_jump_on_damage")?,
+        Op::SetHealth(health) => writeln!(wtr, "set_health {}", health)?,
+        Op::IncrementSprite => writeln!(wtr, "increment_sprite")?,
+        Op::DecrementSprite => writeln!(wtr, "decrement_sprite")?,
+        Op::SetPart(part) => writeln!(wtr, "set_part {}", part)?,
+        Op::RandomizeX(mask) => writeln!(wtr, "randomize_x {:#04X}", mask)?,
+        Op::RandomizeY(mask) => writeln!(wtr, "randomize_y {:#04X}", mask)?,
+        Op::BccX(addr) => writeln!(wtr, "bcc_x {}", label(addr))?,
+        Op::BcsX(addr) => writeln!(wtr, "bcs_x {}", label(addr))?,
+        Op::BccY(addr) => writeln!(wtr, "bcc_y {}", label(addr))?,
+        Op::BcsY(addr) => writeln!(wtr, "bcs_y {}", label(addr))?,
+        Op::ShootAim(unused) => writeln!(wtr, "shoot_aim {}", unused)?,
+        Op::RestoreMusic => writeln!(wtr, "restore_music")?,
+        Op::PlaySound(sound) => writeln!(wtr, "play_sound {}", sound)?,
+    }
+
+    Ok(())
+}
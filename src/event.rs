@@ -0,0 +1,179 @@
+use crate::direction::Direction;
+use crate::game::*;
+use crate::interpret::*;
+
+/// step_events() などに渡す、ゲーム側への問い合わせ。
+/// Game から副作用を伴うコールバックを除いたもの。
+///
+/// Environment と Rng を実装した型は自動的に Input となる。
+pub trait Input: Environment + Rng {}
+
+impl<T: Environment + Rng> Input for T {}
+
+/// Game のコールバックの代わりに記録されるイベント。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    ShootAim {
+        x: u8,
+        y: u8,
+        speed_mask: u8,
+        force_homing: bool,
+    },
+    ShootDirection {
+        x: u8,
+        y: u8,
+        dir: Direction,
+    },
+
+    RestoreMusic,
+    PlaySound(u8),
+    DamageSound,
+    DestructionSound,
+
+    Left,      // 画面外に出て消滅した
+    Destroyed, // 撃破された
+}
+
+impl Interpreter {
+    /// step() と同様だが、Game のコールバックの代わりに events にイベントを追加する。
+    pub fn step_events<I: Input>(
+        &mut self,
+        input: &mut I,
+        events: &mut Vec<Event>,
+    ) -> InterpretResult<()> {
+        let state_before = self.state();
+        let mut game = EventGame { input, events };
+        let res = self.step(&mut game);
+        game.push_state_event(state_before, self.state());
+        res
+    }
+
+    /// damage() と同様だが、Game のコールバックの代わりに events にイベントを追加する。
+    pub fn damage_events<I: Input>(
+        &mut self,
+        input: &mut I,
+        events: &mut Vec<Event>,
+    ) -> InterpretResult<()> {
+        let state_before = self.state();
+        let mut game = EventGame { input, events };
+        let res = self.damage(&mut game);
+        game.push_state_event(state_before, self.state());
+        res
+    }
+}
+
+/// Input と イベント列を Game として扱うためのアダプタ。
+struct EventGame<'a, I> {
+    input: &'a mut I,
+    events: &'a mut Vec<Event>,
+}
+
+impl<I> EventGame<'_, I> {
+    /// Alive から他の状態に遷移していれば対応するイベントを追加する。
+    fn push_state_event(&mut self, before: EnemyState, after: EnemyState) {
+        match (before, after) {
+            (EnemyState::Alive, EnemyState::Dying) => self.events.push(Event::Destroyed),
+            (EnemyState::Alive, EnemyState::Leaving) => self.events.push(Event::Left),
+            _ => {}
+        }
+    }
+}
+
+impl<I: Input> Environment for EventGame<'_, I> {
+    fn is_second_round(&self) -> bool {
+        self.input.is_second_round()
+    }
+    fn stage(&self) -> u8 {
+        self.input.stage()
+    }
+
+    fn hero_x(&self) -> u8 {
+        self.input.hero_x()
+    }
+    fn hero_y(&self) -> u8 {
+        self.input.hero_y()
+    }
+
+    fn rank(&self) -> Option<u8> {
+        self.input.rank()
+    }
+}
+
+impl<I: Input> Rng for EventGame<'_, I> {
+    fn rand(&mut self) -> u8 {
+        self.input.rand()
+    }
+}
+
+impl<I> Shooter for EventGame<'_, I> {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        self.events.try_shoot_aim(x, y, speed_mask, force_homing);
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction) {
+        self.events.try_shoot_direction(x, y, dir);
+    }
+}
+
+impl<I> Audio for EventGame<'_, I> {
+    fn restore_music(&mut self) {
+        self.events.restore_music();
+    }
+    fn play_sound(&mut self, sound: u8) {
+        self.events.play_sound(sound);
+    }
+
+    fn play_damage_sound(&mut self) {
+        self.events.play_damage_sound();
+    }
+    fn play_destruction_sound(&mut self) {
+        self.events.play_destruction_sound();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn left_is_pushed_when_leaving() {
+        let mut interp = InterpreterInit {
+            y: 200,
+            ..interpreter_init("L:\nmove 0x08\njump L\n")
+        }
+        .init()
+        .unwrap();
+        let mut input = game(());
+
+        let mut events = vec![];
+        while interp.state() == EnemyState::Alive {
+            assert!(events.is_empty());
+            interp.step_events(&mut input, &mut events).unwrap();
+        }
+        assert_eq!(interp.state(), EnemyState::Leaving);
+        assert_eq!(events, [Event::Left]);
+
+        // 消滅後は何も追加しない。
+        events.clear();
+        assert!(interp.step_events(&mut input, &mut events).is_err());
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn destroyed_is_pushed_only_when_dying() {
+        let mut input = game(());
+
+        let mut interp = interpreter("L:\nmove 0x04\njump L\n");
+        let mut events = vec![];
+        interp.damage_events(&mut input, &mut events).unwrap();
+        assert_eq!(events, [Event::DestructionSound, Event::Destroyed]);
+
+        // jump_on_damage が設定されていれば撃破されない。
+        let mut interp = interpreter("set_jump_on_damage L\nL:\nmove 0x04\njump L\n");
+        let mut events = vec![];
+        interp.step_events(&mut input, &mut events).unwrap();
+        interp.damage_events(&mut input, &mut events).unwrap();
+        assert_eq!(events, [Event::DamageSound]);
+    }
+}
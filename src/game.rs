@@ -0,0 +1,378 @@
+use crate::direction::Direction;
+use crate::event::Event;
+
+/// ゲーム側の状態への問い合わせ。
+pub trait Environment {
+    fn is_second_round(&self) -> bool;
+    fn stage(&self) -> u8; // 面 (1..=16)
+
+    fn hero_x(&self) -> u8;
+    fn hero_y(&self) -> u8;
+
+    /// 現在のランク (0..=7)。Some なら、インタプリタは毎フレームこの値で自身のランクを更新する。
+    /// None ならインタプリタのランクは変わらない。
+    fn rank(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// 乱数生成器。
+pub trait Rng {
+    fn rand(&mut self) -> u8;
+}
+
+/// 敵弾の発射要求を受け付ける。
+pub trait Shooter {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool);
+
+    // dir は速度指定込み (0..=0x3F) で、そのまま Direction::displacement_bullet に渡せる。
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction);
+}
+
+/// サウンド関連の要求を受け付ける。
+pub trait Audio {
+    fn restore_music(&mut self);
+    fn play_sound(&mut self, sound: u8);
+
+    fn play_damage_sound(&mut self); // 被弾音
+    fn play_destruction_sound(&mut self); // 撃破音
+}
+
+/// インタプリタが必要とする機能全て。
+///
+/// Environment, Rng, Shooter, Audio を全て実装した型は自動的に Game となる。
+/// 個別の実装を組み合わせたい場合は GameParts を使う。
+pub trait Game: Environment + Rng + Shooter + Audio {}
+
+impl<T: Environment + Rng + Shooter + Audio> Game for T {}
+
+/// 各機能の実装を組み合わせて Game とするためのアダプタ。
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameParts<E, R, S, A> {
+    pub env: E,
+    pub rng: R,
+    pub shooter: S,
+    pub audio: A,
+}
+
+impl<E: Environment, R, S, A> Environment for GameParts<E, R, S, A> {
+    fn is_second_round(&self) -> bool {
+        self.env.is_second_round()
+    }
+    fn stage(&self) -> u8 {
+        self.env.stage()
+    }
+
+    fn hero_x(&self) -> u8 {
+        self.env.hero_x()
+    }
+    fn hero_y(&self) -> u8 {
+        self.env.hero_y()
+    }
+
+    fn rank(&self) -> Option<u8> {
+        self.env.rank()
+    }
+}
+
+impl<E, R: Rng, S, A> Rng for GameParts<E, R, S, A> {
+    fn rand(&mut self) -> u8 {
+        self.rng.rand()
+    }
+}
+
+impl<E, R, S: Shooter, A> Shooter for GameParts<E, R, S, A> {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        self.shooter.try_shoot_aim(x, y, speed_mask, force_homing);
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction) {
+        self.shooter.try_shoot_direction(x, y, dir);
+    }
+}
+
+impl<E, R, S, A: Audio> Audio for GameParts<E, R, S, A> {
+    fn restore_music(&mut self) {
+        self.audio.restore_music();
+    }
+    fn play_sound(&mut self, sound: u8) {
+        self.audio.play_sound(sound);
+    }
+
+    fn play_damage_sound(&mut self) {
+        self.audio.play_damage_sound();
+    }
+    fn play_destruction_sound(&mut self) {
+        self.audio.play_destruction_sound();
+    }
+}
+
+/// 固定の面・周回・自機位置・ランクを返す Environment。
+///
+/// フィールドは pub なので、フレームの合間に書き換えれば値を変化させられる。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedEnvironment {
+    pub stage: u8,
+    pub second_round: bool,
+    pub hero_x: u8,
+    pub hero_y: u8,
+    pub rank: Option<u8>,
+}
+
+impl Default for FixedEnvironment {
+    fn default() -> Self {
+        Self {
+            stage: 1,
+            second_round: false,
+            hero_x: 128,
+            hero_y: 200,
+            rank: None,
+        }
+    }
+}
+
+impl Environment for FixedEnvironment {
+    fn is_second_round(&self) -> bool {
+        self.second_round
+    }
+    fn stage(&self) -> u8 {
+        self.stage
+    }
+
+    fn hero_x(&self) -> u8 {
+        self.hero_x
+    }
+    fn hero_y(&self) -> u8 {
+        self.hero_y
+    }
+
+    fn rank(&self) -> Option<u8> {
+        self.rank
+    }
+}
+
+/// シードを指定できる 16bit xorshift 乱数生成器。
+///
+/// 原作の乱数ルーチンの代用であり、原作の乱数列は再現しない
+/// (原作の乱数列を再現したい場合は RecordedRng を使う)。
+/// 状態は state() で取得、set_state() で復元できる。
+///
+/// TODO: 原作の乱数ルーチン (状態の RAM 配置と初期化を含む) を解析し、ビット単位で一致する Rng を実装する。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeededRng {
+    state: u16,
+}
+
+impl SeededRng {
+    /// xorshift の状態は 0 にできないので、seed が 0 なら 1 とみなす。
+    pub fn new(seed: u16) -> Self {
+        Self { state: seed.max(1) }
+    }
+}
+
+impl SeededRng {
+    pub fn state(&self) -> u16 {
+        self.state
+    }
+
+    /// 状態を設定する。0 は 1 とみなす。
+    pub fn set_state(&mut self, state: u16) {
+        self.state = state.max(1);
+    }
+}
+
+impl Default for SeededRng {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Rng for SeededRng {
+    fn rand(&mut self) -> u8 {
+        self.state ^= self.state << 7;
+        self.state ^= self.state >> 9;
+        self.state ^= self.state << 8;
+        self.state as u8
+    }
+}
+
+/// 記録済みの乱数列を順に返す乱数生成器。
+///
+/// エミュレータ等で実プレイ時の rand() の戻り値を記録しておけば、同じ乱数列を再生できる。
+/// 列を使い切ったら先頭に戻る。列が空なら常に 0 を返す。
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedRng {
+    values: Vec<u8>,
+    pos: usize,
+    exhausted: bool,
+}
+
+impl RecordedRng {
+    pub fn new(values: Vec<u8>) -> Self {
+        Self {
+            values,
+            pos: 0,
+            exhausted: false,
+        }
+    }
+
+    pub fn values(&self) -> &[u8] {
+        &self.values
+    }
+
+    /// 次に返す値の位置。
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// 次に返す値の位置を設定する。列の長さで剰余をとる。
+    pub fn set_pos(&mut self, pos: usize) {
+        self.pos = if self.values.is_empty() {
+            0
+        } else {
+            pos % self.values.len()
+        };
+    }
+
+    /// 列を一周以上消費したかどうか。
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+impl Rng for RecordedRng {
+    fn rand(&mut self) -> u8 {
+        if self.values.is_empty() {
+            return 0;
+        }
+
+        let value = self.values[self.pos];
+        self.pos += 1;
+        if self.pos == self.values.len() {
+            self.pos = 0;
+            self.exhausted = true;
+        }
+        value
+    }
+}
+
+/// 何もしない Shooter。
+impl Shooter for () {
+    fn try_shoot_aim(&mut self, _x: u8, _y: u8, _speed_mask: u8, _force_homing: bool) {}
+    fn try_shoot_direction(&mut self, _x: u8, _y: u8, _dir: Direction) {}
+}
+
+/// 何もしない Audio。
+impl Audio for () {
+    fn restore_music(&mut self) {}
+    fn play_sound(&mut self, _sound: u8) {}
+
+    fn play_damage_sound(&mut self) {}
+    fn play_destruction_sound(&mut self) {}
+}
+
+/// 発射要求を Event として記録する Shooter。
+impl Shooter for Vec<Event> {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        self.push(Event::ShootAim {
+            x,
+            y,
+            speed_mask,
+            force_homing,
+        });
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction) {
+        self.push(Event::ShootDirection { x, y, dir });
+    }
+}
+
+/// サウンド要求を Event として記録する Audio。
+impl Audio for Vec<Event> {
+    fn restore_music(&mut self) {
+        self.push(Event::RestoreMusic);
+    }
+    fn play_sound(&mut self, sound: u8) {
+        self.push(Event::PlaySound(sound));
+    }
+
+    fn play_damage_sound(&mut self) {
+        self.push(Event::DamageSound);
+    }
+    fn play_destruction_sound(&mut self) {
+        self.push(Event::DestructionSound);
+    }
+}
+
+impl<T: Environment + ?Sized> Environment for &T {
+    fn is_second_round(&self) -> bool {
+        (**self).is_second_round()
+    }
+    fn stage(&self) -> u8 {
+        (**self).stage()
+    }
+
+    fn hero_x(&self) -> u8 {
+        (**self).hero_x()
+    }
+    fn hero_y(&self) -> u8 {
+        (**self).hero_y()
+    }
+
+    fn rank(&self) -> Option<u8> {
+        (**self).rank()
+    }
+}
+
+impl<T: Environment + ?Sized> Environment for &mut T {
+    fn is_second_round(&self) -> bool {
+        (**self).is_second_round()
+    }
+    fn stage(&self) -> u8 {
+        (**self).stage()
+    }
+
+    fn hero_x(&self) -> u8 {
+        (**self).hero_x()
+    }
+    fn hero_y(&self) -> u8 {
+        (**self).hero_y()
+    }
+
+    fn rank(&self) -> Option<u8> {
+        (**self).rank()
+    }
+}
+
+impl<T: Rng + ?Sized> Rng for &mut T {
+    fn rand(&mut self) -> u8 {
+        (**self).rand()
+    }
+}
+
+impl<T: Shooter + ?Sized> Shooter for &mut T {
+    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool) {
+        (**self).try_shoot_aim(x, y, speed_mask, force_homing);
+    }
+    fn try_shoot_direction(&mut self, x: u8, y: u8, dir: Direction) {
+        (**self).try_shoot_direction(x, y, dir);
+    }
+}
+
+impl<T: Audio + ?Sized> Audio for &mut T {
+    fn restore_music(&mut self) {
+        (**self).restore_music();
+    }
+    fn play_sound(&mut self, sound: u8) {
+        (**self).play_sound(sound);
+    }
+
+    fn play_damage_sound(&mut self) {
+        (**self).play_damage_sound();
+    }
+    fn play_destruction_sound(&mut self) {
+        (**self).play_destruction_sound();
+    }
+}
@@ -0,0 +1,248 @@
+use thiserror::Error;
+
+use crate::bullet::Bullet;
+
+/// シミュレーション用の自機の動きのモデル。
+///
+/// 各フレームで敵は position() を自機位置として参照し、その後 step() で自機が 1 フレーム分動く。
+pub trait HeroModel {
+    fn position(&self) -> (u8, u8);
+
+    /// 1 フレーム進める。bullets は現在の敵弾。
+    fn step(&mut self, bullets: &[Bullet]);
+}
+
+impl<T: HeroModel + ?Sized> HeroModel for Box<T> {
+    fn position(&self) -> (u8, u8) {
+        (**self).position()
+    }
+
+    fn step(&mut self, bullets: &[Bullet]) {
+        (**self).step(bullets);
+    }
+}
+
+/// 自機が入れる y 座標の上限 (敵と同様、y >= 239 は画面外)。
+const HERO_Y_MAX: u8 = 238;
+
+/// 1 軸について from から to へ最大 speed だけ近づける。
+fn approach(from: u8, to: u8, speed: u8) -> u8 {
+    if from < to {
+        from.saturating_add(speed).min(to)
+    } else {
+        from.saturating_sub(speed).max(to)
+    }
+}
+
+/// 動かない自機。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StationaryHero {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl HeroModel for StationaryHero {
+    fn position(&self) -> (u8, u8) {
+        (self.x, self.y)
+    }
+
+    fn step(&mut self, _bullets: &[Bullet]) {}
+}
+
+/// 経由点を順に辿る自機。
+///
+/// 1 フレームに各軸最大 speed だけ次の経由点へ近づく。
+/// 最後の経由点に着いたら、looping なら最初の経由点へ向かい、さもなくばそこに留まる。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaypointHero {
+    x: u8,
+    y: u8,
+    waypoints: Vec<(u8, u8)>,
+    next: usize,
+    speed: u8,
+    looping: bool,
+}
+
+impl WaypointHero {
+    /// 初期位置は waypoints[0] となる。waypoints は空であってはならない。
+    pub fn new(waypoints: Vec<(u8, u8)>, speed: u8, looping: bool) -> Self {
+        assert!(!waypoints.is_empty());
+
+        let (x, y) = waypoints[0];
+        Self {
+            x,
+            y,
+            next: 1 % waypoints.len(),
+            waypoints,
+            speed,
+            looping,
+        }
+    }
+
+    pub fn waypoints(&self) -> &[(u8, u8)] {
+        &self.waypoints
+    }
+}
+
+impl HeroModel for WaypointHero {
+    fn position(&self) -> (u8, u8) {
+        (self.x, self.y)
+    }
+
+    fn step(&mut self, _bullets: &[Bullet]) {
+        let (tx, ty) = self.waypoints[self.next];
+        self.x = approach(self.x, tx, self.speed);
+        self.y = approach(self.y, ty, self.speed);
+
+        if (self.x, self.y) == (tx, ty) {
+            if self.next + 1 < self.waypoints.len() {
+                self.next += 1;
+            } else if self.looping {
+                self.next = 0;
+            }
+        }
+    }
+}
+
+/// 記録されたフレームごとの自機位置を再生する自機。
+///
+/// 記録を使い切ったら最後の位置に留まる。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedHero {
+    positions: Vec<(u8, u8)>,
+    frame: usize,
+}
+
+impl RecordedHero {
+    /// positions は空であってはならない。
+    pub fn new(positions: Vec<(u8, u8)>) -> Self {
+        assert!(!positions.is_empty());
+
+        Self {
+            positions,
+            frame: 0,
+        }
+    }
+
+    /// (x, y) のバイト対が並んだ列から作る。端数のバイトは無視する。
+    /// 位置が 1 つもなければ None を返す。
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let positions: Vec<_> = buf.chunks_exact(2).map(|xy| (xy[0], xy[1])).collect();
+        (!positions.is_empty()).then(|| Self::new(positions))
+    }
+
+    pub fn positions(&self) -> &[(u8, u8)] {
+        &self.positions
+    }
+}
+
+impl HeroModel for RecordedHero {
+    fn position(&self) -> (u8, u8) {
+        self.positions[self.frame.min(self.positions.len() - 1)]
+    }
+
+    fn step(&mut self, _bullets: &[Bullet]) {
+        self.frame += 1;
+    }
+}
+
+/// 近くの敵弾から逃げ、危険がなければ定位置へ戻る単純な自機 AI。
+///
+/// 各軸のチェビシェフ距離が danger_radius 以内の弾を脅威とみなし、
+/// 脅威から遠ざかる向きに各軸最大 speed だけ動く。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DodgingHero {
+    pub x: u8,
+    pub y: u8,
+
+    /// 定位置。
+    pub home_x: u8,
+    pub home_y: u8,
+
+    pub speed: u8,
+    pub danger_radius: u8,
+}
+
+impl DodgingHero {
+    /// 初期位置を定位置とする。
+    pub fn new(x: u8, y: u8, speed: u8, danger_radius: u8) -> Self {
+        Self {
+            x,
+            y,
+            home_x: x,
+            home_y: y,
+            speed,
+            danger_radius,
+        }
+    }
+}
+
+impl HeroModel for DodgingHero {
+    fn position(&self) -> (u8, u8) {
+        (self.x, self.y)
+    }
+
+    fn step(&mut self, bullets: &[Bullet]) {
+        // 脅威から逃げる向きを、近い弾ほど重く合計する。
+        let (mut away_x, mut away_y) = (0_i32, 0_i32);
+        for bullet in bullets {
+            let dx = i32::from(self.x) - i32::from(bullet.x);
+            let dy = i32::from(self.y) - i32::from(bullet.y);
+            let dist = dx.abs().max(dy.abs());
+            if dist > i32::from(self.danger_radius) {
+                continue;
+            }
+
+            let weight = i32::from(self.danger_radius) - dist + 1;
+            // 真上・真下からの弾は横に避ける。
+            let sx = if dx == 0 { 1 } else { dx.signum() };
+            away_x += weight * sx;
+            away_y += weight * dy.signum();
+        }
+
+        if (away_x, away_y) == (0, 0) {
+            self.x = approach(self.x, self.home_x, self.speed);
+            self.y = approach(self.y, self.home_y, self.speed);
+            return;
+        }
+
+        let speed = self.speed;
+        let shift = |pos: u8, away: i32| match away.signum() {
+            1 => pos.saturating_add(speed),
+            -1 => pos.saturating_sub(speed),
+            _ => pos,
+        };
+        self.x = shift(self.x, away_x);
+        self.y = shift(self.y, away_y).min(HERO_Y_MAX);
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid waypoints (expected \"x,y x,y ...\"): {0}")]
+pub struct ParseWaypointsError(String);
+
+/// "x,y x,y ..." 形式の経由点列をパースする。区切りは空白または ';'。
+pub fn parse_waypoints(s: &str) -> Result<Vec<(u8, u8)>, ParseWaypointsError> {
+    let err = || ParseWaypointsError(s.to_owned());
+
+    let waypoints = s
+        .split(|c: char| c.is_ascii_whitespace() || c == ';')
+        .filter(|tok| !tok.is_empty())
+        .map(|tok| {
+            let (x, y) = tok.split_once(',').ok_or_else(err)?;
+            let x = x.trim().parse().map_err(|_| err())?;
+            let y = y.trim().parse().map_err(|_| err())?;
+            Ok((x, y))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if waypoints.is_empty() {
+        return Err(err());
+    }
+
+    Ok(waypoints)
+}
@@ -0,0 +1,140 @@
+use crate::game::*;
+use crate::interpret::*;
+
+/// 一定フレームごとに Interpreter とゲーム側 (G) のスナップショットを記録し、任意フレームの状態を再構築する。
+///
+/// フレーム n の状態とは、初期状態から step() を n 回呼んだ後の状態を指す。
+/// 再構築はスナップショットからの再実行で行う。
+/// ゲーム側もスナップショットから復元するので、乱数の状態などは記録時と同じになる。
+/// 再実行中にゲーム側の入力 (自機位置など) を変える必要がある場合は restore_with() を使う。
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History<G> {
+    interval: usize,
+    snapshots: Vec<(InterpreterRegisters, G)>, // snapshots[i] はフレーム i * interval の状態
+}
+
+impl<G: Clone> History<G> {
+    pub fn new(interval: usize) -> Self {
+        assert!(interval > 0);
+
+        Self {
+            interval,
+            snapshots: vec![],
+        }
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// 記録済みの最終フレームを返す。
+    pub fn last_frame(&self) -> Option<usize> {
+        self.snapshots
+            .len()
+            .checked_sub(1)
+            .map(|i| i * self.interval)
+    }
+
+    /// フレーム frame の状態として interp と game を記録する。
+    /// 記録が必要なフレームでなければ何もしない。
+    pub fn record(&mut self, frame: usize, interp: &Interpreter, game: &G) {
+        if frame == self.snapshots.len() * self.interval {
+            self.snapshots.push((interp.registers(), game.clone()));
+        }
+    }
+
+    /// フレーム frame 以前で最も近いスナップショットを (フレーム, 敵の状態, ゲーム側の状態) として返す。
+    pub fn nearest(&self, frame: usize) -> Option<(usize, &InterpreterRegisters, &G)> {
+        let i = (frame / self.interval).min(self.snapshots.len().checked_sub(1)?);
+        let (regs, game) = &self.snapshots[i];
+        Some((i * self.interval, regs, game))
+    }
+
+    /// フレーム frame より後のスナップショットを破棄する (スクリプトを編集した場合など)。
+    pub fn truncate(&mut self, frame: usize) {
+        self.snapshots.truncate(frame / self.interval + 1);
+    }
+}
+
+impl<G: Game + Clone> History<G> {
+    /// interp と game をフレーム frame の状態にする。
+    /// 途中で敵が消滅した場合、その時点の状態で止まる。
+    ///
+    /// スナップショットが 1 つもない場合は何もせず false を返す。
+    pub fn restore(
+        &self,
+        interp: &mut Interpreter,
+        game: &mut G,
+        frame: usize,
+    ) -> InterpretResult<bool> {
+        self.restore_with(interp, game, frame, |_, _| {})
+    }
+
+    /// restore() と同様だが、再実行する各フレームの直前に before_step(フレーム, game) を呼ぶ。
+    /// 記録時にフレームごとに自機位置などを更新していた場合、ここで同じ更新を行う。
+    pub fn restore_with<F: FnMut(usize, &mut G)>(
+        &self,
+        interp: &mut Interpreter,
+        game: &mut G,
+        frame: usize,
+        mut before_step: F,
+    ) -> InterpretResult<bool> {
+        let (frame_snapshot, regs, game_snapshot) = match self.nearest(frame) {
+            Some(snapshot) => snapshot,
+            None => return Ok(false),
+        };
+
+        interp.set_registers(regs)?;
+        *game = game_snapshot.clone();
+        for f in frame_snapshot..frame {
+            if !matches!(interp.state(), EnemyState::Alive) {
+                break;
+            }
+            before_step(f, game);
+            interp.step(game)?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn restore_reproduces_rng_dependent_frames() {
+        const SRC: &str = "L:\nrandomize_x 0xFF\nmove 0x08\njump L\n";
+
+        let mut interp = InterpreterInit {
+            y: 0,
+            ..interpreter_init(SRC)
+        }
+        .init()
+        .unwrap();
+        let mut game = GameParts {
+            rng: SeededRng::new(42),
+            ..game(())
+        };
+
+        let mut history = History::new(4);
+        let mut expected = vec![];
+        for frame in 0..20 {
+            history.record(frame, &interp, &game);
+            expected.push((interp.registers(), game.rng));
+            interp.step(&mut game).unwrap();
+        }
+
+        for frame in (0..20).rev() {
+            assert!(history.restore(&mut interp, &mut game, frame).unwrap());
+            assert_eq!(
+                (interp.registers(), game.rng),
+                expected[frame],
+                "frame {}",
+                frame
+            );
+        }
+    }
+}
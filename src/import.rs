@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use thiserror::Error;
+
+use crate::asm::*;
+use crate::disasm::*;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("line {lineno}: parse error: {msg}")]
+    Parse { lineno: usize, msg: String },
+
+    #[error("line {lineno}: undefined label: {label}")]
+    UndefinedLabel { lineno: usize, label: String },
+
+    #[error("line {lineno}: duplicate label: {label}")]
+    DuplicateLabel { lineno: usize, label: String },
+
+    #[error("line {lineno}: code size overflow")]
+    Overflow { lineno: usize },
+
+    #[error("disassemble failed")]
+    Disasm(#[from] DisasmError),
+
+    #[error("imported listing does not reassemble: {0}")]
+    Reassemble(#[source] AsmError),
+
+    #[error("imported listing reassembles to different bytes")]
+    ReassembleMismatch,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type ImportResult<T> = Result<T, ImportError>;
+
+/// ca65/asm6 形式の .byte リスト (ラベル、コメント付き) を読み込み、
+/// 本クレートのアセンブリとして書き出す。
+///
+/// 認識するのはラベル定義 (`name:`, `@name:`)、`.byte`/`.db` ディレクティブ、コメントのみで、
+/// バイトを出力しないディレクティブ (`.segment`, `.export` など) は無視する。
+/// それ以外のディレクティブ (`.word`, `.res`, `.incbin` など) や 6502 の命令はエラーになる。
+/// `.byte` の値には数値 (`$41`, `%0101`, `0x41`, `65`, `'A'`) またはラベル名を書ける。
+/// ラベル名はリスト先頭からのオフセットとして解決される。
+///
+/// ca65 のチープローカルラベル (`@name`) は本クレートのローカルラベル (`.name`) として書き出す。
+/// 書き出したアセンブリは再アセンブルして元のバイト列と一致することを確認する
+/// (ローカルラベルのスコープが保てない場合などはエラーになる)。
+pub fn import_listing<R: std::io::Read, W: Write>(rdr: R, mut wtr: W) -> ImportResult<()> {
+    use std::io::BufRead as _;
+
+    let rdr = std::io::BufReader::new(rdr);
+
+    let mut buf = Vec::<u8>::new();
+    let mut annotations = Annotations::default();
+    let mut label_to_addr = HashMap::<String, usize>::new();
+    let mut unresolved = Vec::<(usize, usize, String)>::new(); // (lineno, addr, label)
+    let mut scope = None;
+
+    for (i, line) in rdr.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line?;
+        let (code, comment) = split_comment(&line);
+
+        let mut code = code.trim();
+        while let Some((label, rest)) = split_label_definition(code) {
+            let (key, name) = if let Some(local) = label.strip_prefix('@') {
+                (
+                    qualify_local(lineno, scope.as_deref(), local)?,
+                    format!(".{}", local),
+                )
+            } else {
+                scope = Some(label.to_owned());
+                (label.to_owned(), label.to_owned())
+            };
+            if label_to_addr.contains_key(&key) {
+                return Err(ImportError::DuplicateLabel {
+                    lineno,
+                    label: label.to_owned(),
+                });
+            }
+            label_to_addr.insert(key, buf.len());
+            annotations
+                .leading
+                .entry(buf.len())
+                .or_default()
+                .push(Leading::Label(name));
+            code = rest.trim_start();
+        }
+
+        let addr_line = buf.len();
+        if let Some(values) = strip_byte_directive(lineno, code)? {
+            for value in values.split(',') {
+                let value = value.trim();
+                if let Some(b) = parse_byte(lineno, value)? {
+                    buf.push(b);
+                } else if is_identifier(value) {
+                    let label = match value.strip_prefix('@') {
+                        Some(local) => qualify_local(lineno, scope.as_deref(), local)?,
+                        None => value.to_owned(),
+                    };
+                    unresolved.push((lineno, buf.len(), label));
+                    buf.push(0);
+                } else {
+                    return Err(ImportError::Parse {
+                        lineno,
+                        msg: format!("invalid byte value: {}", value),
+                    });
+                }
+            }
+            if buf.len() > 0x100 {
+                return Err(ImportError::Overflow { lineno });
+            }
+        }
+
+        if let Some(comment) = comment {
+            if buf.len() > addr_line {
+                annotations
+                    .trailing
+                    .entry(addr_line)
+                    .or_default()
+                    .push(comment.to_owned());
+            } else {
+                annotations
+                    .leading
+                    .entry(addr_line)
+                    .or_default()
+                    .push(Leading::Comment(comment.to_owned()));
+            }
+        }
+    }
+
+    for (lineno, addr, label) in unresolved {
+        let addr_dst = *label_to_addr
+            .get(&label)
+            .ok_or(ImportError::UndefinedLabel { lineno, label })?;
+        buf[addr] = addr_dst as u8;
+    }
+
+    let mut text = Vec::<u8>::new();
+    disasm_annotated(&mut text, &buf, &annotations)?;
+
+    let code = asm(text.as_slice()).map_err(ImportError::Reassemble)?;
+    if code != buf {
+        return Err(ImportError::ReassembleMismatch);
+    }
+
+    wtr.write_all(&text)?;
+
+    Ok(())
+}
+
+/// チープローカルラベルを直前のラベル名で修飾する。
+/// "." は識別子に使えないので、修飾した名前がグローバルラベルと衝突することはない。
+fn qualify_local(lineno: usize, scope: Option<&str>, local: &str) -> ImportResult<String> {
+    let scope = scope.ok_or_else(|| ImportError::Parse {
+        lineno,
+        msg: format!("local label without preceding label: @{}", local),
+    })?;
+    Ok(format!("{}.{}", scope, local))
+}
+
+/// 行をコード部とコメント部 (';' の直後から) に分ける。
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut in_quote = false;
+    for (pos, c) in line.char_indices() {
+        match c {
+            '\'' | '"' => in_quote = !in_quote,
+            ';' if !in_quote => return (&line[..pos], Some(&line[pos + 1..])),
+            _ => {}
+        }
+    }
+    (line, None)
+}
+
+/// 行頭のラベル定義 "name:" を (name, 残り) に分ける。
+fn split_label_definition(code: &str) -> Option<(&str, &str)> {
+    let pos = code.find(':')?;
+    let label = &code[..pos];
+    if is_identifier(label) {
+        Some((label, &code[pos + 1..]))
+    } else {
+        None
+    }
+}
+
+/// .byte 系ディレクティブなら値の部分を返す。
+/// 空行やバイトを出力しないディレクティブなら None、それ以外はエラーを返す。
+fn strip_byte_directive(lineno: usize, code: &str) -> ImportResult<Option<&str>> {
+    const DIRECTIVES: &[&str] = &[".byte", ".byt", ".db", "db", "byte"];
+    const IGNORED: &[&str] = &[
+        ".segment",
+        ".code",
+        ".rodata",
+        ".data",
+        ".export",
+        ".exportzp",
+        ".import",
+        ".importzp",
+        ".global",
+        ".globalzp",
+    ];
+
+    let end = code.find(char::is_whitespace).unwrap_or(code.len());
+    let (directive, values) = code.split_at(end);
+
+    if directive.is_empty() || IGNORED.iter().any(|d| d.eq_ignore_ascii_case(directive)) {
+        Ok(None)
+    } else if DIRECTIVES.iter().any(|d| d.eq_ignore_ascii_case(directive)) {
+        Ok(Some(values))
+    } else {
+        Err(ImportError::Parse {
+            lineno,
+            msg: format!("unsupported directive or instruction: {}", directive),
+        })
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let s = s.strip_prefix('@').unwrap_or(s);
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 数値リテラルをパースする。数値でなければ None を返す。
+fn parse_byte(lineno: usize, s: &str) -> ImportResult<Option<u8>> {
+    let value = if let Some(hex) = s.strip_prefix('$') {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix('%') {
+        u32::from_str_radix(bin, 2).ok()
+    } else if s.len() == 3 && s.starts_with('\'') && s.ends_with('\'') {
+        Some(u32::from(s.as_bytes()[1]))
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.parse::<u32>().ok()
+    } else {
+        return Ok(None);
+    };
+
+    match value {
+        Some(value) if value <= 0xFF => Ok(Some(value as u8)),
+        _ => Err(ImportError::Parse {
+            lineno,
+            msg: format!("byte value must be 0..=255: {}", s),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(src: &str) -> ImportResult<String> {
+        let mut text = Vec::<u8>::new();
+        import_listing(src.as_bytes(), &mut text)?;
+        Ok(String::from_utf8(text).unwrap())
+    }
+
+    fn import_err(src: &str) -> String {
+        import(src).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn generated_labels_keep_local_scope() {
+        const SRC: &str = "\
+Enemy1:
+.byte $11
+@loop: .byte $11
+.byte $40,$05
+.byte $11
+.byte $40,@loop
+";
+        const EXPECTED: &str = "\
+Enemy1:
+        move 0x11
+.loop:
+        move 0x11
+        jump .L05
+        move 0x11
+.L05:
+        jump .loop
+";
+
+        assert_eq!(import(SRC).unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn references_across_scopes_are_qualified() {
+        const SRC: &str = "\
+Enemy1:
+@loop: .byte $11
+.byte $40,$05
+Enemy2:
+.byte $40,@loop
+@loop: .byte $40,$00
+";
+        const EXPECTED: &str = "\
+Enemy1:
+.loop:
+        move 0x11
+        jump Enemy2.loop
+Enemy2:
+        jump .loop
+.loop:
+        jump Enemy1
+";
+
+        assert_eq!(import(SRC).unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        const SRC: &str = "\
+; header
+.segment \"RODATA\"
+Enemy1:
+.byte $11, $12 ; move twice
+.byte ';'      ; not a comment
+; tail
+";
+        const EXPECTED: &str = "\
+; header
+Enemy1:
+        move 0x11                ; move twice
+        move 0x12
+        move 0x3B                ; not a comment
+; tail
+";
+
+        assert_eq!(import(SRC).unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn unsupported_lines_are_rejected() {
+        for (src, msg) in &[
+            (
+                ".word $1234\n",
+                "line 1: parse error: unsupported directive or instruction: .word",
+            ),
+            (".res 4\n", "unsupported directive or instruction: .res"),
+            (
+                ".incbin \"enemy.bin\"\n",
+                "unsupported directive or instruction: .incbin",
+            ),
+            ("A: lda #$00\n", "unsupported directive or instruction: lda"),
+            (".byte $100\n", "byte value must be 0..=255: $100"),
+            (".byte +1\n", "invalid byte value: +1"),
+            (".byte $40,B\n", "line 1: undefined label: B"),
+            ("A: .byte $11\nA: .byte $11\n", "line 2: duplicate label: A"),
+            (
+                "@loop: .byte $11\n",
+                "local label without preceding label: @loop",
+            ),
+        ] {
+            let err = import_err(src);
+            assert!(err.contains(msg), "{:?}: {}", src, err);
+        }
+    }
+}
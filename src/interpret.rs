@@ -1,23 +1,12 @@
+use std::collections::VecDeque;
+
 use thiserror::Error;
 
+use crate::clip::ClipConfig;
 use crate::direction::Direction;
+use crate::game::*;
 use crate::op::*;
 
-pub trait Game {
-    fn is_second_round(&self) -> bool;
-    fn stage(&self) -> u8; // 面 (1..=16)
-
-    fn hero_x(&self) -> u8;
-    fn hero_y(&self) -> u8;
-
-    fn rand(&mut self) -> u8;
-
-    fn try_shoot_aim(&mut self, x: u8, y: u8, speed_mask: u8, force_homing: bool);
-
-    fn restore_music(&mut self);
-    fn play_sound(&mut self, sound: u8);
-}
-
 #[derive(Debug, Error)]
 pub enum InterpretError {
     #[error("address {addr:#04X}: decode failed")]
@@ -26,10 +15,94 @@ pub enum InterpretError {
         #[source]
         source: DecodeError,
     },
+
+    #[error("address {addr:#04X}: program counter out of bounds")]
+    PcOutOfBounds { addr: usize },
+
+    #[error("enemy is not alive: {state:?}")]
+    NotAlive { state: EnemyState },
+
+    #[error("address {addr:#04X}: {op:?} is not permitted (boss={boss})")]
+    ModeMismatch { addr: usize, op: Op, boss: bool },
+
+    #[error("address {addr:#04X}: sprite index overflow")]
+    SpriteOverflow { addr: usize },
+
+    #[error("rank must be within 0..=7: {rank}")]
+    InvalidRank { rank: u8 },
+
+    // trail は直近に実行した命令のアドレス (最大 BUDGET_TRAIL_LEN 個、古い順)。
+    #[error("op budget exceeded ({budget} ops in a frame), pc trail: {trail:02X?}")]
+    BudgetExceeded { budget: usize, trail: Vec<usize> },
 }
 
 pub type InterpretResult<T> = Result<T, InterpretError>;
 
+/// InterpretError::BudgetExceeded に含める直近の命令アドレスの数。
+pub const BUDGET_TRAIL_LEN: usize = 32;
+
+/// step_traced() で実行された処理の情報。
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpTrace {
+    // 命令ならそのアドレス、さもなくば処理時点の pc。
+    pub addr: usize,
+    pub action: Action,
+
+    // Move, ShootDirection, ホーミング移動の実効方向 (ランクによる加速/弾速指定込み)。
+    // 弾を撃たなかった場合は None。
+    pub dir: Option<Direction>,
+
+    pub pos_before: (u8, u8),
+    pub pos_after: (u8, u8),
+
+    // この命令でフレームの処理を終えた場合、その理由。
+    pub yield_reason: Option<YieldReason>,
+}
+
+/// OpTrace が表す処理。
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Op(Op),
+    Homing, // ホーミング移動 (命令の実行前に行われる)
+    Sleep,  // スリープタイマーのカウントダウン (命令を実行せずにフレームを終える)
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Op(op) => op.fmt(f),
+            Self::Homing => f.write_str("(homing)"),
+            Self::Sleep => f.write_str("(sleep)"),
+        }
+    }
+}
+
+/// 命令の実行後、そのフレームの処理を終えた理由。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum YieldReason {
+    Move,
+    Leave, // 移動して画面外に出た
+    Sleep,
+    SetJumpOnDamage, // unset_jump_on_damage も含む
+    SetHealth,
+}
+
+/// 実行した命令ごとに呼ばれるオブザーバー。
+/// () は何もしない実装で、step() はこれを使う。
+pub trait Tracer {
+    fn trace(&mut self, trace: &OpTrace);
+}
+
+impl Tracer for () {
+    fn trace(&mut self, _trace: &OpTrace) {}
+}
+
+impl<F: FnMut(&OpTrace)> Tracer for F {
+    fn trace(&mut self, trace: &OpTrace) {
+        self(trace)
+    }
+}
+
 #[derive(Debug)]
 pub struct InterpreterInit {
     pub program: Vec<u8>,
@@ -46,13 +119,19 @@ pub struct InterpreterInit {
 
     pub x: u8,
     pub y: u8,
+
+    pub op_budget: Option<usize>, // 1 フレームに実行できる命令数の上限 (None なら無制限)
+
+    pub clip: ClipConfig, // 画面外判定の規則 (通常は ClipConfig::ROM)
 }
 
 impl InterpreterInit {
-    pub fn init(self) -> Interpreter {
-        assert!((0..=7).contains(&self.rank));
+    pub fn init(self) -> InterpretResult<Interpreter> {
+        if !(0..=7).contains(&self.rank) {
+            return Err(InterpretError::InvalidRank { rank: self.rank });
+        }
 
-        Interpreter {
+        Ok(Interpreter {
             program: self.program,
             pc: self.pc,
 
@@ -64,6 +143,8 @@ impl InterpreterInit {
             extra_act_with_rank: self.extra_act_with_rank,
             accel_with_rank: self.accel_with_rank,
             rank: self.rank,
+            op_budget: self.op_budget,
+            clip: self.clip,
 
             state: EnemyState::Alive,
             x: self.x,
@@ -79,18 +160,68 @@ impl InterpreterInit {
             loop_start_addr: self.pc,
             loop_counter: 0,
             jump_on_damage: 0,
-        }
+
+            frame: None,
+        })
     }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EnemyState {
     Alive,
     Dying,
     Leaving,
 }
 
-#[derive(Debug)]
+/// Interpreter の実行中に変化する内部状態。
+/// プログラムや設定を含まないので、スナップショットとしてコンパクトに保存できる。
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterpreterRegisters {
+    pub pc: usize,
+
+    pub state: EnemyState,
+    pub x: u8,
+    pub y: u8,
+    pub inv_x: bool,
+    pub inv_y: bool,
+    pub health: u8,
+    pub sprite_idx: u8,
+    pub part: u8,
+
+    pub sleep_timer: u8,
+    pub homing_timer: u8,
+    pub loop_start_addr: usize,
+    pub loop_counter: u8,
+    pub jump_on_damage: u8,
+
+    pub rank: u8,
+}
+
+/// step_op() で途中まで実行したフレームの状態。
+#[derive(Clone, Debug)]
+struct Frame {
+    do_try_homing: bool,
+    do_try_extra_act: bool,
+
+    // 実行した命令数と、直近の命令のアドレス (命令数上限の検査用)
+    ops: usize,
+    trail: VecDeque<usize>,
+}
+
+impl Frame {
+    fn new(do_try_extra_act: bool) -> Self {
+        Self {
+            do_try_homing: true,
+            do_try_extra_act,
+            ops: 0,
+            trail: VecDeque::new(), // 命令数上限がなければ確保されない
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Interpreter {
     program: Vec<u8>,
     pc: usize,
@@ -103,6 +234,8 @@ pub struct Interpreter {
     extra_act_with_rank: bool,
     accel_with_rank: bool,
     rank: u8,
+    op_budget: Option<usize>,
+    clip: ClipConfig,
 
     state: EnemyState,
     x: u8,
@@ -118,191 +251,321 @@ pub struct Interpreter {
     loop_start_addr: usize,
     loop_counter: u8,
     jump_on_damage: u8,
+
+    frame: Option<Frame>,
 }
 
 impl Interpreter {
     pub fn step<G: Game>(&mut self, game: &mut G) -> InterpretResult<()> {
-        assert!(matches!(self.state, EnemyState::Alive));
+        self.step_traced(game, &mut ())
+    }
 
-        if self.sleep_timer > 0 {
-            self.sleep_timer -= 1;
-            return Ok(());
+    /// step() と同様だが、実行した処理ごとに tracer を呼び出す。
+    ///
+    /// step_op() でフレームの途中まで実行していた場合、その続きからフレームの終わりまで実行する。
+    pub fn step_traced<G: Game, T: Tracer>(
+        &mut self,
+        game: &mut G,
+        tracer: &mut T,
+    ) -> InterpretResult<()> {
+        loop {
+            let trace = self.step_op(game)?;
+            tracer.trace(&trace);
+            if trace.yield_reason.is_some() {
+                return Ok(());
+            }
         }
+    }
 
-        let mut do_try_homing = true;
-        let mut do_try_extra_act = self.extra_act_with_rank;
+    /// 命令 1 つ (またはホーミング移動、スリープのカウントダウン) だけ実行する。
+    ///
+    /// 返り値の yield_reason が Some ならフレームの処理を終えている。
+    /// さもなくばフレームの途中であり、次の step_op() (または step()) で続きを実行する。
+    /// エラーの場合、実行中のフレームは打ち切られる。
+    pub fn step_op<G: Game>(&mut self, game: &mut G) -> InterpretResult<OpTrace> {
+        let mut frame = match self.frame.take() {
+            Some(frame) => frame,
+            None => {
+                self.ensure_alive()?;
 
-        loop {
-            // ホーミング処理(基本的には1回のみ)
-            if do_try_homing && self.homing_timer > 0 {
-                self.homing_timer -= 1;
-                let dir = Direction::aim((self.x, self.y), (game.hero_x(), game.hero_y()));
-                let (dx, dy) = dir.displacement_object();
-                self.x = self.x.wrapping_add(dx as u8);
-                self.y = self.y.wrapping_add(dy as u8);
-                let extra_act = self.clip(game, &mut do_try_extra_act);
-                if extra_act {
-                    continue;
-                } else {
-                    return Ok(());
+                // ランクはフレームごとにゲーム側から取得できる。
+                if let Some(rank) = game.rank() {
+                    self.set_rank(rank)?;
+                }
+
+                if self.sleep_timer > 0 {
+                    self.sleep_timer -= 1;
+                    return Ok(OpTrace {
+                        addr: self.pc,
+                        action: Action::Sleep,
+                        dir: None,
+                        pos_before: (self.x, self.y),
+                        pos_after: (self.x, self.y),
+                        yield_reason: Some(YieldReason::Sleep),
+                    });
                 }
+
+                Frame::new(self.extra_act_with_rank)
             }
-            do_try_homing = false;
+        };
 
-            let op = self.fetch()?;
+        let trace = self.exec_one(game, &mut frame)?;
+        if trace.yield_reason.is_none() {
+            self.frame = Some(frame);
+        }
 
-            match op {
-                Op::Move(dir) => {
-                    // 低速移動は特定条件下で高速化
-                    let dir = if (0..=0x1F).contains(&dir.index()) {
-                        if self.cond_accel1(game) {
-                            Direction::new(dir.index() + 0x10)
-                        } else if self.cond_accel2(game) {
-                            Direction::new(dir.index() + 0x20)
-                        } else {
-                            dir
-                        }
+        Ok(trace)
+    }
+
+    /// フレームの途中 (step_op() で実行を止めている) かどうかを返す。
+    pub fn is_mid_frame(&self) -> bool {
+        self.frame.is_some()
+    }
+
+    /// 次の step_op() で命令を実行するかどうか (ホーミング移動やスリープのカウントダウンでないか) を返す。
+    /// 命令を実行する場合、その命令のアドレスは pc() である。
+    pub fn will_execute_op(&self) -> bool {
+        match &self.frame {
+            Some(frame) => !(frame.do_try_homing && self.homing_timer > 0),
+            None => self.sleep_timer == 0 && self.homing_timer == 0,
+        }
+    }
+
+    fn exec_one<G: Game>(&mut self, game: &mut G, frame: &mut Frame) -> InterpretResult<OpTrace> {
+        // ホーミング処理(基本的には1回のみ。再行動した場合は再び行う)
+        if frame.do_try_homing && self.homing_timer > 0 {
+            self.homing_timer -= 1;
+            let pos_before = (self.x, self.y);
+            let dir = Direction::aim((self.x, self.y), (game.hero_x(), game.hero_y()));
+            let overflowed = !self.displace(dir.displacement_object());
+            let extra_act = self.clip(game, &mut frame.do_try_extra_act, overflowed);
+            let yield_reason = if extra_act {
+                None
+            } else {
+                Some(self.move_yield_reason())
+            };
+            return Ok(OpTrace {
+                addr: self.pc,
+                action: Action::Homing,
+                dir: Some(dir),
+                pos_before,
+                pos_after: (self.x, self.y),
+                yield_reason,
+            });
+        }
+        frame.do_try_homing = false;
+
+        let addr = self.pc;
+        if let Some(budget) = self.op_budget {
+            if frame.ops >= budget {
+                return Err(InterpretError::BudgetExceeded {
+                    budget,
+                    trail: frame.trail.iter().copied().collect(),
+                });
+            }
+            frame.ops += 1;
+            if frame.trail.len() == BUDGET_TRAIL_LEN {
+                frame.trail.pop_front();
+            }
+            frame.trail.push_back(addr);
+        }
+        let op = self.fetch()?;
+
+        let pos_before = (self.x, self.y);
+        let mut dir_effective = None;
+        let mut yield_reason = None;
+
+        match op {
+            Op::Move(dir) => {
+                // 低速移動は特定条件下で高速化
+                let dir = if (0..=0x1F).contains(&dir.index()) {
+                    if self.cond_accel1(game) {
+                        Direction::new(dir.index() + 0x10)
+                    } else if self.cond_accel2(game) {
+                        Direction::new(dir.index() + 0x20)
                     } else {
                         dir
-                    };
-                    let (dx, dy) = dir.displacement_object();
-                    let dx = if self.inv_x { -dx } else { dx };
-                    let dy = if self.inv_y { -dy } else { dy };
-                    self.x = self.x.wrapping_add(dx as u8);
-                    self.y = self.y.wrapping_add(dy as u8);
-                    let extra_act = self.clip(game, &mut do_try_extra_act);
-                    if !extra_act {
-                        return Ok(());
-                    }
-                }
-                Op::Jump(addr) => {
-                    self.pc = usize::from(addr);
-                }
-                Op::SetSleepTimer(idx) => {
-                    self.sleep_timer = 4 * idx;
-                    return Ok(());
-                }
-                Op::LoopBegin(idx) => {
-                    self.loop_start_addr = self.pc;
-                    self.loop_counter = idx;
-                }
-                Op::LoopEnd => {
-                    self.loop_counter = self.loop_counter.wrapping_sub(1);
-                    if self.loop_counter > 0 {
-                        self.pc = self.loop_start_addr;
                     }
+                } else {
+                    dir
+                };
+                dir_effective = Some(dir);
+                let (dx, dy) = dir.displacement_object();
+                let dx = if self.inv_x { -dx } else { dx };
+                let dy = if self.inv_y { -dy } else { dy };
+                let overflowed = !self.displace((dx, dy));
+                let extra_act = self.clip(game, &mut frame.do_try_extra_act, overflowed);
+                if !extra_act {
+                    yield_reason = Some(self.move_yield_reason());
                 }
-                Op::ShootDirection(_dir) => {
-                    todo!();
-                }
-                Op::SetSprite(idx) => {
-                    self.sprite_idx = idx;
-                }
-                Op::SetHomingTimer(idx) => {
-                    self.homing_timer = if idx == 0 { 252 } else { 4 * idx };
-                    do_try_homing = true;
-                }
-                Op::SetInversion(inv_x, inv_y) => {
-                    self.inv_x = inv_x;
-                    self.inv_y = inv_y;
-                }
-                Op::SetPosition(x, y) => {
-                    self.x = x;
-                    self.y = y;
-                }
-                Op::SetJumpOnDamage(addr) => {
-                    assert!(!self.boss);
-                    self.jump_on_damage = addr;
-                    return Ok(());
-                }
-                Op::UnsetJumpOnDamage => {
-                    assert!(!self.boss);
-                    self.jump_on_damage = 0;
-                    return Ok(());
-                }
-                Op::SetHealth(health) => {
-                    assert!(self.boss);
-                    self.health = health;
-                    return Ok(());
-                }
-                Op::IncrementSprite => {
-                    self.sprite_idx += 1;
-                }
-                Op::DecrementSprite => {
-                    self.sprite_idx -= 1;
-                }
-                Op::SetPart(part) => {
-                    self.part = part;
-                }
-                Op::RandomizeX(mask) => {
-                    self.x = (self.x & !mask) | (game.rand() & mask);
+            }
+            Op::Jump(addr) => {
+                self.pc = usize::from(addr);
+            }
+            Op::SetSleepTimer(idx) => {
+                self.sleep_timer = 4 * idx;
+                yield_reason = Some(YieldReason::Sleep);
+            }
+            Op::LoopBegin(idx) => {
+                self.loop_start_addr = self.pc;
+                self.loop_counter = idx;
+            }
+            Op::LoopEnd => {
+                self.loop_counter = self.loop_counter.wrapping_sub(1);
+                if self.loop_counter > 0 {
+                    self.pc = self.loop_start_addr;
                 }
-                Op::RandomizeY(mask) => {
-                    self.y = (self.y & !mask) | (game.rand() & mask);
+            }
+            Op::ShootDirection(dir) => {
+                if self.cond_shoot_aim() {
+                    let dir = Direction::new(dir.index() | self.shoot_speed_mask());
+                    dir_effective = Some(dir);
+                    game.try_shoot_direction(self.x, self.y, dir);
                 }
-                Op::BccX(addr) => {
-                    if self.x < game.hero_x() {
-                        self.pc = usize::from(addr);
-                    }
+            }
+            Op::SetSprite(idx) => {
+                self.sprite_idx = idx;
+            }
+            Op::SetHomingTimer(idx) => {
+                self.homing_timer = if idx == 0 { 252 } else { 4 * idx };
+                frame.do_try_homing = true;
+            }
+            Op::SetInversion(inv_x, inv_y) => {
+                self.inv_x = inv_x;
+                self.inv_y = inv_y;
+            }
+            Op::SetPosition(x, y) => {
+                self.x = x;
+                self.y = y;
+            }
+            Op::SetJumpOnDamage(addr_dst) => {
+                self.ensure_mode(addr, op, false)?;
+                self.jump_on_damage = addr_dst;
+                yield_reason = Some(YieldReason::SetJumpOnDamage);
+            }
+            Op::UnsetJumpOnDamage => {
+                self.ensure_mode(addr, op, false)?;
+                self.jump_on_damage = 0;
+                yield_reason = Some(YieldReason::SetJumpOnDamage);
+            }
+            Op::SetHealth(health) => {
+                self.ensure_mode(addr, op, true)?;
+                self.health = health;
+                yield_reason = Some(YieldReason::SetHealth);
+            }
+            Op::IncrementSprite => {
+                self.sprite_idx = self
+                    .sprite_idx
+                    .checked_add(1)
+                    .ok_or(InterpretError::SpriteOverflow { addr })?;
+            }
+            Op::DecrementSprite => {
+                self.sprite_idx = self
+                    .sprite_idx
+                    .checked_sub(1)
+                    .ok_or(InterpretError::SpriteOverflow { addr })?;
+            }
+            Op::SetPart(part) => {
+                self.part = part;
+            }
+            Op::RandomizeX(mask) => {
+                self.x = (self.x & !mask) | (game.rand() & mask);
+            }
+            Op::RandomizeY(mask) => {
+                self.y = (self.y & !mask) | (game.rand() & mask);
+            }
+            Op::BccX(addr) => {
+                if self.x < game.hero_x() {
+                    self.pc = usize::from(addr);
                 }
-                Op::BcsX(addr) => {
-                    if self.x >= game.hero_x() {
-                        self.pc = usize::from(addr);
-                    }
+            }
+            Op::BcsX(addr) => {
+                if self.x >= game.hero_x() {
+                    self.pc = usize::from(addr);
                 }
-                Op::BccY(addr) => {
-                    if self.y < game.hero_y() {
-                        self.pc = usize::from(addr);
-                    }
+            }
+            Op::BccY(addr) => {
+                if self.y < game.hero_y() {
+                    self.pc = usize::from(addr);
                 }
-                Op::BcsY(addr) => {
-                    if self.y >= game.hero_y() {
-                        self.pc = usize::from(addr);
-                    }
+            }
+            Op::BcsY(addr) => {
+                if self.y >= game.hero_y() {
+                    self.pc = usize::from(addr);
                 }
-                Op::ShootAim(_) => {
-                    if !self.cond_shoot_aim() {
-                        continue;
-                    }
+            }
+            Op::ShootAim(_) => {
+                if self.cond_shoot_aim() {
                     let (speed_mask, force_homing) = self.shoot_aim_param(game);
                     game.try_shoot_aim(self.x, self.y, speed_mask, force_homing);
                 }
-                Op::RestoreMusic => {
-                    game.restore_music();
-                }
-                Op::PlaySound(sound) => {
-                    game.play_sound(sound);
-                }
+            }
+            Op::RestoreMusic => {
+                game.restore_music();
+            }
+            Op::PlaySound(sound) => {
+                game.play_sound(sound);
             }
         }
+
+        Ok(OpTrace {
+            addr,
+            action: Action::Op(op),
+            dir: dir_effective,
+            pos_before,
+            pos_after: (self.x, self.y),
+            yield_reason,
+        })
     }
 
-    pub fn damage<G: Game>(&mut self, _game: &mut G) {
-        assert!(matches!(self.state, EnemyState::Alive));
+    /// 被弾処理。
+    ///
+    /// ボスの場合、HP が 0 なら撃破、さもなくば HP を 1 減らす。
+    /// ザコの場合、被弾時のジャンプ先が設定されていればそこへジャンプし、さもなくば撃破。
+    pub fn damage<G: Game>(&mut self, game: &mut G) -> InterpretResult<()> {
+        self.ensure_alive()?;
 
-        if self.boss {
+        let destroyed = if self.boss {
             if self.health == 0 {
-                self.state = EnemyState::Dying;
-                // TODO: 本来は撃破音が鳴る
+                true
             } else {
                 self.health -= 1;
-                // TODO: 本来はダメージ音が鳴る
+                false
             }
+        } else if self.jump_on_damage == 0 {
+            true
         } else {
-            if self.jump_on_damage == 0 {
-                self.state = EnemyState::Dying;
-                // TODO: 本来は撃破音が鳴る
-            } else {
-                self.pc = usize::from(self.jump_on_damage);
-                // TODO: 本来はダメージ音が鳴る
-            }
+            self.pc = usize::from(self.jump_on_damage);
+            false
+        };
+
+        if destroyed {
+            self.state = EnemyState::Dying;
+            game.play_destruction_sound();
+        } else {
+            game.play_damage_sound();
         }
+
+        Ok(())
     }
 
     pub fn state(&self) -> EnemyState {
         self.state
     }
 
+    pub(crate) fn set_state(&mut self, state: EnemyState) {
+        self.state = state;
+    }
+
+    pub(crate) fn set_clip(&mut self, clip: ClipConfig) {
+        self.clip = clip;
+    }
+
+    pub(crate) fn set_position(&mut self, x: u8, y: u8) {
+        self.x = x;
+        self.y = y;
+    }
+
     pub fn x(&self) -> u8 {
         self.x
     }
@@ -315,8 +578,177 @@ impl Interpreter {
         self.sprite_idx
     }
 
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn is_boss(&self) -> bool {
+        self.boss
+    }
+
+    pub fn difficulty(&self) -> u8 {
+        self.difficulty
+    }
+
+    pub fn shot_with_rank(&self) -> bool {
+        self.shot_with_rank
+    }
+
+    pub fn accel_shot_with_rank(&self) -> bool {
+        self.accel_shot_with_rank
+    }
+
+    pub fn homing_shot_with_rank(&self) -> bool {
+        self.homing_shot_with_rank
+    }
+
+    pub fn extra_act_with_rank(&self) -> bool {
+        self.extra_act_with_rank
+    }
+
+    pub fn accel_with_rank(&self) -> bool {
+        self.accel_with_rank
+    }
+
+    pub fn rank(&self) -> u8 {
+        self.rank
+    }
+
+    /// ランクを変更する。0..=7 でなければエラーを返し、何も変更しない。
+    pub fn set_rank(&mut self, rank: u8) -> InterpretResult<()> {
+        if !(0..=7).contains(&rank) {
+            return Err(InterpretError::InvalidRank { rank });
+        }
+
+        self.rank = rank;
+        Ok(())
+    }
+
+    pub fn op_budget(&self) -> Option<usize> {
+        self.op_budget
+    }
+
+    pub fn clip_config(&self) -> &ClipConfig {
+        &self.clip
+    }
+
+    pub fn inversion(&self) -> (bool, bool) {
+        (self.inv_x, self.inv_y)
+    }
+
+    pub fn health(&self) -> u8 {
+        self.health
+    }
+
+    pub fn part(&self) -> u8 {
+        self.part
+    }
+
+    pub fn sleep_timer(&self) -> u8 {
+        self.sleep_timer
+    }
+
+    pub fn homing_timer(&self) -> u8 {
+        self.homing_timer
+    }
+
+    pub fn loop_start_addr(&self) -> usize {
+        self.loop_start_addr
+    }
+
+    pub fn loop_counter(&self) -> u8 {
+        self.loop_counter
+    }
+
+    /// 被弾時のジャンプ先を返す (0 なら未設定)。
+    pub fn jump_on_damage(&self) -> u8 {
+        self.jump_on_damage
+    }
+
+    /// 実行中に変化する内部状態をまとめて取り出す。
+    pub fn registers(&self) -> InterpreterRegisters {
+        InterpreterRegisters {
+            pc: self.pc,
+            state: self.state,
+            x: self.x,
+            y: self.y,
+            inv_x: self.inv_x,
+            inv_y: self.inv_y,
+            health: self.health,
+            sprite_idx: self.sprite_idx,
+            part: self.part,
+            sleep_timer: self.sleep_timer,
+            homing_timer: self.homing_timer,
+            loop_start_addr: self.loop_start_addr,
+            loop_counter: self.loop_counter,
+            jump_on_damage: self.jump_on_damage,
+            rank: self.rank,
+        }
+    }
+
+    /// registers() で取り出した内部状態を書き戻す。実行途中のフレームは破棄する。
+    /// pc, loop_start_addr がプログラム外を指す場合や rank が不正な場合はエラーを返し、何も変更しない。
+    pub fn set_registers(&mut self, regs: &InterpreterRegisters) -> InterpretResult<()> {
+        for &addr in &[regs.pc, regs.loop_start_addr] {
+            if addr > self.program.len() {
+                return Err(InterpretError::PcOutOfBounds { addr });
+            }
+        }
+        if !(0..=7).contains(&regs.rank) {
+            return Err(InterpretError::InvalidRank { rank: regs.rank });
+        }
+
+        self.pc = regs.pc;
+        self.state = regs.state;
+        self.x = regs.x;
+        self.y = regs.y;
+        self.inv_x = regs.inv_x;
+        self.inv_y = regs.inv_y;
+        self.health = regs.health;
+        self.sprite_idx = regs.sprite_idx;
+        self.part = regs.part;
+        self.sleep_timer = regs.sleep_timer;
+        self.homing_timer = regs.homing_timer;
+        self.loop_start_addr = regs.loop_start_addr;
+        self.loop_counter = regs.loop_counter;
+        self.jump_on_damage = regs.jump_on_damage;
+        self.rank = regs.rank;
+        self.frame = None;
+
+        Ok(())
+    }
+
+    fn ensure_alive(&self) -> InterpretResult<()> {
+        if matches!(self.state, EnemyState::Alive) {
+            Ok(())
+        } else {
+            Err(InterpretError::NotAlive { state: self.state })
+        }
+    }
+
+    /// op がボス/ザコの区別に合っているか検査する。
+    fn ensure_mode(&self, addr: usize, op: Op, boss: bool) -> InterpretResult<()> {
+        if self.boss == boss {
+            Ok(())
+        } else {
+            Err(InterpretError::ModeMismatch {
+                addr,
+                op,
+                boss: self.boss,
+            })
+        }
+    }
+
     fn fetch(&mut self) -> InterpretResult<Op> {
-        let mut op = Op::decode(&self.program[self.pc..]).map_err(|e| InterpretError::Decode {
+        let buf = match self.program.get(self.pc..) {
+            Some(buf) if !buf.is_empty() => buf,
+            _ => return Err(InterpretError::PcOutOfBounds { addr: self.pc }),
+        };
+        let mut op = Op::decode(buf).map_err(|e| InterpretError::Decode {
             addr: self.pc,
             source: e,
         })?;
@@ -331,13 +763,33 @@ impl Interpreter {
         Ok(op)
     }
 
-    /// 画面外に出たら消滅させる。
+    /// 移動でフレームを終える場合の理由を返す。
+    fn move_yield_reason(&self) -> YieldReason {
+        if matches!(self.state, EnemyState::Leaving) {
+            YieldReason::Leave
+        } else {
+            YieldReason::Move
+        }
+    }
+
+    /// ClipConfig の Overflow に従って (dx, dy) だけ移動する。
+    /// Overflow::Leave の軸で範囲を越えた場合は移動せずに false を返す。
+    fn displace(&mut self, d: (i8, i8)) -> bool {
+        match self.clip.displace((self.x, self.y), d) {
+            Some((x, y)) => {
+                self.x = x;
+                self.y = y;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 画面外に出たら (overflowed が真の場合も含む) 消滅させる。
     /// また、do_try_extra_act が真の場合、再行動条件を満たしているか判定する。
     /// 再行動するかどうかを返す。
-    fn clip<G: Game>(&mut self, game: &G, do_try_extra_act: &mut bool) -> bool {
-        const CLIP_Y_MIN: u8 = 239;
-
-        if self.y >= CLIP_Y_MIN {
+    fn clip<G: Game>(&mut self, game: &G, do_try_extra_act: &mut bool, overflowed: bool) -> bool {
+        if overflowed || self.clip.is_outside((self.x, self.y)) {
             self.state = EnemyState::Leaving;
             return false;
         }
@@ -360,10 +812,17 @@ impl Interpreter {
         // 誘導弾にする場合、スピード指定マスクは 0 にする。
         if self.homing_shot_with_rank && game.is_second_round() && self.rank == 7 {
             (0, true)
-        } else if self.accel_shot_with_rank {
-            ((self.rank << 3) & 0x30, false)
         } else {
-            (0, false)
+            (self.shoot_speed_mask(), false)
+        }
+    }
+
+    /// 弾のスピード指定マスクを返す。
+    fn shoot_speed_mask(&self) -> u8 {
+        if self.accel_shot_with_rank {
+            (self.rank << 3) & 0x30
+        } else {
+            0
         }
     }
 
@@ -377,3 +836,259 @@ impl Interpreter {
         self.accel_with_rank && game.stage() >= self.difficulty && self.rank == 7
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bullet::Bullets;
+    use crate::event::Event;
+    use crate::testutil::*;
+
+    fn interpreter_with_rank(
+        src: &str,
+        shot_with_rank: bool,
+        accel_shot_with_rank: bool,
+        rank: u8,
+    ) -> Interpreter {
+        InterpreterInit {
+            shot_with_rank,
+            accel_shot_with_rank,
+            rank,
+            ..interpreter_init(src)
+        }
+        .init()
+        .unwrap()
+    }
+
+    /// 1 フレーム実行し、発射された弾のイベントを返す。
+    fn step_shots(interp: &mut Interpreter) -> Vec<Event> {
+        let mut game = game(Vec::<Event>::new());
+        interp.step(&mut game).unwrap();
+        game.shooter
+    }
+
+    /// 1 フレーム実行し、トレースされた処理と終了理由を返す。
+    fn step_actions(interp: &mut Interpreter) -> Vec<(Action, Option<YieldReason>)> {
+        let mut game = game(());
+        let mut actions = vec![];
+        interp
+            .step_traced(&mut game, &mut |trace: &OpTrace| {
+                actions.push((trace.action, trace.yield_reason))
+            })
+            .unwrap();
+        actions
+    }
+
+    const SHOOT_RIGHT: &str = "shoot_direction 4\nmove 0x04\n";
+
+    #[test]
+    fn shoot_direction_gated_by_rank() {
+        let mut interp = interpreter_with_rank(SHOOT_RIGHT, true, false, 3);
+        assert!(step_shots(&mut interp).is_empty());
+
+        let mut interp = interpreter_with_rank(SHOOT_RIGHT, true, false, 4);
+        assert_eq!(
+            step_shots(&mut interp),
+            [Event::ShootDirection {
+                x: 100,
+                y: 100,
+                dir: Direction::new(0x04),
+            }]
+        );
+
+        // shot_with_rank でなければランクによらず撃つ。
+        let mut interp = interpreter_with_rank(SHOOT_RIGHT, false, false, 0);
+        assert_eq!(step_shots(&mut interp).len(), 1);
+    }
+
+    #[test]
+    fn shoot_direction_speed_mask_with_rank() {
+        for &(rank, dir) in &[(0, 0x04), (2, 0x14), (4, 0x24), (6, 0x34), (7, 0x34)] {
+            let mut interp = interpreter_with_rank(SHOOT_RIGHT, false, true, rank);
+            assert_eq!(
+                step_shots(&mut interp),
+                [Event::ShootDirection {
+                    x: 100,
+                    y: 100,
+                    dir: Direction::new(dir),
+                }],
+                "rank {}",
+                rank
+            );
+        }
+
+        // accel_shot_with_rank でなければ速度指定は付かない。
+        let mut interp = interpreter_with_rank(SHOOT_RIGHT, false, false, 7);
+        assert_eq!(
+            step_shots(&mut interp),
+            [Event::ShootDirection {
+                x: 100,
+                y: 100,
+                dir: Direction::new(0x04),
+            }]
+        );
+    }
+
+    #[test]
+    fn shoot_direction_moves_bullet_by_displacement_bullet() {
+        for &(rank, dx) in &[(0, 2), (2, 3), (4, 4), (6, 5)] {
+            let mut interp = interpreter_with_rank(SHOOT_RIGHT, false, true, rank);
+            let events = step_shots(&mut interp);
+
+            let mut bullets = Bullets::default();
+            assert!(bullets.shoot_event(&events[0]));
+            bullets.step();
+
+            let bullet = bullets.bullets()[0];
+            assert_eq!(bullet.dir.displacement_bullet(), (dx, 0), "rank {}", rank);
+            assert_eq!((bullet.x, bullet.y), (100 + dx as u8, 100), "rank {}", rank);
+        }
+    }
+
+    #[test]
+    fn homing_and_sleep_are_traced() {
+        let mut interp = interpreter("set_homing_timer 1\nset_sleep_timer 1\n");
+
+        assert_eq!(
+            step_actions(&mut interp),
+            [
+                (Action::Op(Op::SetHomingTimer(1)), None),
+                (Action::Homing, Some(YieldReason::Move)),
+            ]
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                step_actions(&mut interp),
+                [(Action::Homing, Some(YieldReason::Move))]
+            );
+        }
+        assert_eq!(
+            step_actions(&mut interp),
+            [(Action::Op(Op::SetSleepTimer(1)), Some(YieldReason::Sleep))]
+        );
+        for _ in 0..4 {
+            assert_eq!(
+                step_actions(&mut interp),
+                [(Action::Sleep, Some(YieldReason::Sleep))]
+            );
+        }
+    }
+
+    #[test]
+    fn step_op_resumes_frame() {
+        const SRC: &str =
+            "set_homing_timer 1\nL:\nset_sprite 2\nmove 0x08\nset_sleep_timer 1\njump L\n";
+
+        let mut by_frame = interpreter(SRC);
+        let mut by_op = by_frame.clone();
+        let mut game = game(());
+
+        for _ in 0..20 {
+            by_frame.step(&mut game).unwrap();
+            loop {
+                let trace = by_op.step_op(&mut game).unwrap();
+                if trace.yield_reason.is_some() {
+                    break;
+                }
+                assert!(by_op.is_mid_frame());
+            }
+            assert!(!by_op.is_mid_frame());
+            assert_eq!(by_op.registers(), by_frame.registers());
+        }
+
+        // フレームの途中から step() を呼ぶと、そのフレームの残りを実行する。
+        let mut interp = interpreter(SRC);
+        interp.step_op(&mut game).unwrap();
+        assert!(interp.is_mid_frame());
+        interp.step(&mut game).unwrap();
+        assert!(!interp.is_mid_frame());
+        assert_eq!(interp.homing_timer(), 3);
+    }
+
+    #[test]
+    fn budget_exceeded_keeps_last_pcs() {
+        let mut interp = InterpreterInit {
+            op_budget: Some(100),
+            ..interpreter_init("L:\nset_sprite 1\njump L\n")
+        }
+        .init()
+        .unwrap();
+
+        match interp.step(&mut game(())) {
+            Err(InterpretError::BudgetExceeded { budget, trail }) => {
+                assert_eq!(budget, 100);
+                let expected: Vec<_> = (0..BUDGET_TRAIL_LEN)
+                    .map(|i| if i % 2 == 0 { 0 } else { 1 })
+                    .collect();
+                assert_eq!(trail, expected);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn pc_past_program_is_an_error() {
+        let mut interp = interpreter("set_sprite 1\n");
+        assert!(matches!(
+            interp.step(&mut game(())),
+            Err(InterpretError::PcOutOfBounds { addr: 1 })
+        ));
+    }
+
+    #[test]
+    fn mode_mismatch_is_an_error() {
+        // 0xA1 はボス/ザコに応じて set_health/set_jump_on_damage と解釈されるので、
+        // ザコの set_health は set_jump_on_damage として実行される。
+        let mut interp = interpreter("set_health 3\n");
+        assert_eq!(
+            interp.step_op(&mut game(())).unwrap().action,
+            Action::Op(Op::SetJumpOnDamage(3))
+        );
+
+        // 命令の実行前の検査はモードの合わない命令を拒否する。
+        let interp = interpreter("");
+        assert!(matches!(
+            interp.ensure_mode(5, Op::SetHealth(3), true),
+            Err(InterpretError::ModeMismatch {
+                addr: 5,
+                op: Op::SetHealth(3),
+                boss: false,
+            })
+        ));
+    }
+
+    #[test]
+    fn sprite_underflow_is_an_error() {
+        let mut interp = interpreter("set_sprite 1\ndecrement_sprite\ndecrement_sprite\n");
+        assert!(matches!(
+            interp.step(&mut game(())),
+            Err(InterpretError::SpriteOverflow { addr: 2 })
+        ));
+    }
+
+    #[test]
+    fn step_after_leaving_is_an_error() {
+        let mut interp = InterpreterInit {
+            y: 238,
+            ..interpreter_init("L:\nmove 0x08\njump L\n")
+        }
+        .init()
+        .unwrap();
+        let mut game = game(());
+
+        interp.step(&mut game).unwrap();
+        assert_eq!(interp.state(), EnemyState::Leaving);
+        assert!(matches!(
+            interp.step(&mut game),
+            Err(InterpretError::NotAlive {
+                state: EnemyState::Leaving
+            })
+        ));
+        assert!(matches!(
+            interp.damage(&mut game),
+            Err(InterpretError::NotAlive {
+                state: EnemyState::Leaving
+            })
+        ));
+    }
+}
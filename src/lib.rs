@@ -1,11 +1,37 @@
 mod asm;
+mod boss;
+mod bullet;
+mod clip;
+mod collision;
 mod direction;
 mod disasm;
+mod event;
+mod game;
+mod hero;
+mod history;
+mod import;
 mod interpret;
 mod op;
+mod output;
+mod rank;
+#[cfg(test)]
+mod testutil;
+mod world;
 
 pub use crate::asm::*;
+pub use crate::boss::*;
+pub use crate::bullet::*;
+pub use crate::clip::*;
+pub use crate::collision::*;
 pub use crate::direction::*;
 pub use crate::disasm::*;
+pub use crate::event::*;
+pub use crate::game::*;
+pub use crate::hero::*;
+pub use crate::history::*;
+pub use crate::import::*;
 pub use crate::interpret::*;
 pub use crate::op::*;
+pub use crate::output::*;
+pub use crate::rank::*;
+pub use crate::world::*;
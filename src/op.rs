@@ -160,6 +160,7 @@ impl Op {
         Self::PlaySound(sound)
     }
 
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(self) -> usize {
         match self {
             Self::Move(..) => 1,
@@ -364,3 +365,38 @@ impl Op {
         }
     }
 }
+
+/// アセンブリ風に表示する。アドレスオペランドはラベルではなく数値で表示する。
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Move(dir) => write!(f, "move {:#04X}", dir.index()),
+            Self::Jump(addr) => write!(f, "jump {:#04X}", addr),
+            Self::SetSleepTimer(idx) => write!(f, "set_sleep_timer {}", idx),
+            Self::LoopBegin(idx) => write!(f, "loop_begin {}", idx),
+            Self::LoopEnd => write!(f, "loop_end"),
+            Self::ShootDirection(dir) => write!(f, "shoot_direction {:#04X}", dir.index()),
+            Self::SetSprite(idx) => write!(f, "set_sprite {}", idx),
+            Self::SetHomingTimer(idx) => write!(f, "set_homing_timer {}", idx),
+            Self::SetInversion(inv_x, inv_y) => {
+                write!(f, "set_inversion {}, {}", u8::from(inv_x), u8::from(inv_y))
+            }
+            Self::SetPosition(x, y) => write!(f, "set_position {}, {}", x, y),
+            Self::SetJumpOnDamage(addr) => write!(f, "set_jump_on_damage {:#04X}", addr),
+            Self::UnsetJumpOnDamage => write!(f, "unset_jump_on_damage"),
+            Self::SetHealth(health) => write!(f, "set_health {}", health),
+            Self::IncrementSprite => write!(f, "increment_sprite"),
+            Self::DecrementSprite => write!(f, "decrement_sprite"),
+            Self::SetPart(part) => write!(f, "set_part {}", part),
+            Self::RandomizeX(mask) => write!(f, "randomize_x {:#04X}", mask),
+            Self::RandomizeY(mask) => write!(f, "randomize_y {:#04X}", mask),
+            Self::BccX(addr) => write!(f, "bcc_x {:#04X}", addr),
+            Self::BcsX(addr) => write!(f, "bcs_x {:#04X}", addr),
+            Self::BccY(addr) => write!(f, "bcc_y {:#04X}", addr),
+            Self::BcsY(addr) => write!(f, "bcs_y {:#04X}", addr),
+            Self::ShootAim(unused) => write!(f, "shoot_aim {}", unused),
+            Self::RestoreMusic => write!(f, "restore_music"),
+            Self::PlaySound(sound) => write!(f, "play_sound {}", sound),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::Write;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("unknown output format: {0}")]
+pub struct ParseOutputFormatError(String);
+
+/// アセンブル結果の出力形式。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OutputFormat {
+    Binary,
+    C,
+    Rust,
+    Byte, // ca65/asm6 の .byte ディレクティブ
+    Hex,
+    IntelHex,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["bin", "c", "rust", "byte", "hex", "ihex"];
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = ParseOutputFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bin" => Ok(Self::Binary),
+            "c" => Ok(Self::C),
+            "rust" => Ok(Self::Rust),
+            "byte" => Ok(Self::Byte),
+            "hex" => Ok(Self::Hex),
+            "ihex" => Ok(Self::IntelHex),
+            _ => Err(ParseOutputFormatError(s.to_owned())),
+        }
+    }
+}
+
+/// buf を指定した形式で書き出す。
+/// name は C/Rust の配列名、および .byte 形式のラベル名として使われる。
+pub fn write_output<W: Write>(
+    mut wtr: W,
+    buf: &[u8],
+    format: OutputFormat,
+    name: &str,
+) -> std::io::Result<()> {
+    const BYTES_PER_LINE: usize = 16;
+
+    match format {
+        OutputFormat::Binary => wtr.write_all(buf)?,
+
+        OutputFormat::C => {
+            writeln!(wtr, "const unsigned char {}[{}] = {{", name, buf.len())?;
+            for chunk in buf.chunks(BYTES_PER_LINE) {
+                writeln!(wtr, "    {},", join_bytes(chunk, "0x", ", "))?;
+            }
+            writeln!(wtr, "}};")?;
+        }
+
+        OutputFormat::Rust => {
+            writeln!(wtr, "pub const {}: [u8; {}] = [", name, buf.len())?;
+            for chunk in buf.chunks(BYTES_PER_LINE) {
+                writeln!(wtr, "    {},", join_bytes(chunk, "0x", ", "))?;
+            }
+            writeln!(wtr, "];")?;
+        }
+
+        OutputFormat::Byte => {
+            writeln!(wtr, "{}:", name)?;
+            for chunk in buf.chunks(BYTES_PER_LINE) {
+                writeln!(wtr, "        .byte {}", join_bytes(chunk, "$", ","))?;
+            }
+        }
+
+        OutputFormat::Hex => {
+            writeln!(wtr, "{}", join_bytes(buf, "", ""))?;
+        }
+
+        OutputFormat::IntelHex => {
+            for (i, chunk) in buf.chunks(BYTES_PER_LINE).enumerate() {
+                write_ihex_record(&mut wtr, (i * BYTES_PER_LINE) as u16, 0x00, chunk)?;
+            }
+            write_ihex_record(&mut wtr, 0, 0x01, &[])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn join_bytes(buf: &[u8], prefix: &str, sep: &str) -> String {
+    buf.iter()
+        .map(|b| format!("{}{:02X}", prefix, b))
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn write_ihex_record<W: Write>(wtr: &mut W, addr: u16, ty: u8, data: &[u8]) -> std::io::Result<()> {
+    let [addr_hi, addr_lo] = addr.to_be_bytes();
+    let len = data.len() as u8;
+
+    let sum = [len, addr_hi, addr_lo, ty]
+        .iter()
+        .chain(data)
+        .fold(0_u8, |acc, &b| acc.wrapping_add(b));
+    let checksum = sum.wrapping_neg();
+
+    writeln!(
+        wtr,
+        ":{:02X}{:04X}{:02X}{}{:02X}",
+        len,
+        addr,
+        ty,
+        join_bytes(data, "", ""),
+        checksum
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(buf: &[u8], format: OutputFormat) -> String {
+        let mut out = vec![];
+        write_output(&mut out, buf, format, "enemy").unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// 2 行にわたる 18 バイト。
+    fn bytes() -> Vec<u8> {
+        (0..18).collect()
+    }
+
+    #[test]
+    fn intel_hex() {
+        assert_eq!(
+            output(&[0x10, 0x40, 0x00], OutputFormat::IntelHex),
+            ":03000000104000AD\n:00000001FF\n"
+        );
+        assert_eq!(
+            output(&bytes(), OutputFormat::IntelHex),
+            "\
+:10000000000102030405060708090A0B0C0D0E0F78
+:020010001011CD
+:00000001FF
+"
+        );
+        assert_eq!(output(&[], OutputFormat::IntelHex), ":00000001FF\n");
+    }
+
+    #[test]
+    fn c_array() {
+        assert_eq!(
+            output(&bytes(), OutputFormat::C),
+            "\
+const unsigned char enemy[18] = {
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    0x10, 0x11,
+};
+"
+        );
+    }
+
+    #[test]
+    fn rust_const() {
+        assert_eq!(
+            output(&bytes(), OutputFormat::Rust),
+            "\
+pub const enemy: [u8; 18] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F,
+    0x10, 0x11,
+];
+"
+        );
+    }
+
+    #[test]
+    fn byte_directives() {
+        assert_eq!(
+            output(&bytes(), OutputFormat::Byte),
+            "\
+enemy:
+        .byte $00,$01,$02,$03,$04,$05,$06,$07,$08,$09,$0A,$0B,$0C,$0D,$0E,$0F
+        .byte $10,$11
+"
+        );
+    }
+
+    #[test]
+    fn hex_and_binary() {
+        assert_eq!(output(&[0x10, 0xAB], OutputFormat::Hex), "10AB\n");
+
+        let mut out = vec![];
+        write_output(&mut out, &bytes(), OutputFormat::Binary, "enemy").unwrap();
+        assert_eq!(out, bytes());
+    }
+}
@@ -0,0 +1,92 @@
+/// プレイ中のランクの上下のモデル。
+///
+/// 一定フレーム生存するごと、または一定数の敵を撃破するごとにランクが上がり、
+/// 自機が被弾するとランクが下がる。
+/// 上昇・下降の条件や量は設定可能。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RankModel {
+    rank: u8,
+
+    /// このフレーム数ごとにランクが 1 上がる。None なら時間では上がらない。
+    pub rise_interval: Option<usize>,
+    /// この数の敵を撃破するごとにランクが 1 上がる。None なら撃破数では上がらない。
+    pub rise_per_kills: Option<usize>,
+    /// 自機の被弾時に下がるランク。
+    pub fall_on_hit: u8,
+
+    frames: usize,
+    kills: usize,
+}
+
+impl RankModel {
+    pub const MAX_RANK: u8 = 7;
+
+    /// rank は 0..=7 に丸められる。
+    pub fn new(
+        rank: u8,
+        rise_interval: Option<usize>,
+        rise_per_kills: Option<usize>,
+        fall_on_hit: u8,
+    ) -> Self {
+        Self {
+            rank: rank.min(Self::MAX_RANK),
+            rise_interval,
+            rise_per_kills,
+            fall_on_hit,
+            frames: 0,
+            kills: 0,
+        }
+    }
+
+    /// ランクが変わらないモデル。
+    pub fn fixed(rank: u8) -> Self {
+        Self::new(rank, None, None, 0)
+    }
+
+    /// 現在のランク (0..=7)。
+    pub fn rank(&self) -> u8 {
+        self.rank
+    }
+
+    /// ランクを設定する。0..=7 に丸められる。
+    pub fn set_rank(&mut self, rank: u8) {
+        self.rank = rank.min(Self::MAX_RANK);
+    }
+
+    /// 1 フレーム経過したことを通知する。
+    pub fn on_frame(&mut self) {
+        self.frames += 1;
+        if let Some(interval) = self.rise_interval {
+            if interval > 0 && self.frames % interval == 0 {
+                self.rise();
+            }
+        }
+    }
+
+    /// 敵を撃破したことを通知する。
+    pub fn on_kill(&mut self) {
+        self.kills += 1;
+        if let Some(n) = self.rise_per_kills {
+            if n > 0 && self.kills % n == 0 {
+                self.rise();
+            }
+        }
+    }
+
+    /// 自機が被弾したことを通知する。時間による上昇の計測もやり直す。
+    pub fn on_hero_hit(&mut self) {
+        self.rank = self.rank.saturating_sub(self.fall_on_hit);
+        self.frames = 0;
+    }
+
+    fn rise(&mut self) {
+        self.rank = (self.rank + 1).min(Self::MAX_RANK);
+    }
+}
+
+impl Default for RankModel {
+    fn default() -> Self {
+        Self::new(0, Some(60 * 30), Some(16), 2)
+    }
+}
@@ -0,0 +1,45 @@
+//! テスト用の共通処理。
+
+use crate::asm::asm;
+use crate::clip::ClipConfig;
+use crate::game::*;
+use crate::interpret::*;
+
+/// src をアセンブルした InterpreterInit を返す。
+/// ザコ、難易度 1、ランク 0、*_with_rank フラグなし、位置 (100, 100)、命令数上限なし。
+pub(crate) fn interpreter_init(src: &str) -> InterpreterInit {
+    InterpreterInit {
+        program: asm(src.as_bytes()).unwrap(),
+        pc: 0,
+
+        boss: false,
+        difficulty: 1,
+        shot_with_rank: false,
+        accel_shot_with_rank: false,
+        homing_shot_with_rank: false,
+        extra_act_with_rank: false,
+        accel_with_rank: false,
+        rank: 0,
+
+        x: 100,
+        y: 100,
+
+        op_budget: None,
+        clip: ClipConfig::ROM,
+    }
+}
+
+/// interpreter_init(src) の Interpreter を返す。
+pub(crate) fn interpreter(src: &str) -> Interpreter {
+    interpreter_init(src).init().unwrap()
+}
+
+/// 環境と乱数生成器が既定値で、発射要求を shooter に渡す Game を返す。
+pub(crate) fn game<S>(shooter: S) -> GameParts<FixedEnvironment, SeededRng, S, ()> {
+    GameParts {
+        env: FixedEnvironment::default(),
+        rng: SeededRng::default(),
+        shooter,
+        audio: (),
+    }
+}
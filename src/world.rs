@@ -0,0 +1,510 @@
+use thiserror::Error;
+
+use crate::bullet::*;
+use crate::collision::*;
+use crate::event::Event;
+use crate::game::*;
+use crate::hero::HeroModel;
+use crate::interpret::*;
+use crate::rank::RankModel;
+
+#[derive(Debug, Error)]
+pub enum WorldError {
+    #[error("slot {slot}: {source}")]
+    Interpret {
+        slot: usize,
+        #[source]
+        source: InterpretError,
+    },
+
+    #[error("slot {slot}: no enemy")]
+    EmptySlot { slot: usize },
+}
+
+pub type WorldResult<T> = Result<T, WorldError>;
+
+/// World 内で発生したイベント。slot は発生元の敵のスロット番号。
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorldEvent {
+    pub slot: usize,
+    pub event: Event,
+}
+
+#[derive(Debug)]
+pub struct WorldInit<R> {
+    pub stage: u8, // 面 (1..=16)
+    pub second_round: bool,
+
+    pub hero_x: u8,
+    pub hero_y: u8,
+
+    /// 敵スロット数。
+    pub max_enemies: usize,
+    pub bullet: BulletConfig,
+
+    /// None なら当たり判定を行わない。
+    pub collision: Option<CollisionConfig>,
+    pub damage_schedule: DamageSchedule,
+
+    /// None なら各敵のランクは InterpreterInit で与えたまま変わらない。
+    pub rank: Option<RankModel>,
+
+    pub rng: R,
+}
+
+impl<R: Rng> WorldInit<R> {
+    pub fn init(self) -> World<R> {
+        World {
+            stage: self.stage,
+            second_round: self.second_round,
+            hero_x: self.hero_x,
+            hero_y: self.hero_y,
+            slots: vec![None; self.max_enemies],
+            bullets: Bullets::new(self.bullet),
+            rng: self.rng,
+            collision: self.collision,
+            damage_schedule: self.damage_schedule,
+            shots: vec![],
+            rank: self.rank,
+            frame: 0,
+            events: vec![],
+            hero_hits: vec![],
+        }
+    }
+}
+
+/// 複数の敵、自機、敵弾、乱数生成器をまとめて進めるシミュレータ。
+///
+/// 敵は固定数のスロットに置かれ、毎フレームスロット番号の昇順に実行される。
+/// 敵が Leaving/Dying になるとそのフレームのうちにスロットは空く。
+#[derive(Clone, Debug)]
+pub struct World<R> {
+    stage: u8,
+    second_round: bool,
+
+    hero_x: u8,
+    hero_y: u8,
+
+    slots: Vec<Option<Interpreter>>,
+    bullets: Bullets,
+    rng: R,
+
+    collision: Option<CollisionConfig>,
+    damage_schedule: DamageSchedule,
+    shots: Vec<(u8, u8)>, // 自機弾
+
+    rank: Option<RankModel>,
+
+    frame: usize,
+    events: Vec<WorldEvent>,
+    hero_hits: Vec<HeroHit>,
+}
+
+impl<R: Rng> World<R> {
+    /// 1 フレーム進める。
+    ///
+    /// 既存の敵弾を動かした後、各スロットの敵を順に実行する。
+    /// 敵の撃った弾はその場で敵弾に加わる。
+    /// その後、予定表に従ったダメージと当たり判定によるダメージを与える。
+    /// events(), hero_hits() はこのフレームのものに置き換わる。
+    pub fn step(&mut self) -> WorldResult<()> {
+        self.frame += 1;
+        self.events.clear();
+        self.hero_hits.clear();
+
+        self.bullets.set_target(self.hero_x, self.hero_y);
+        self.bullets.step();
+
+        for slot in 0..self.slots.len() {
+            if self.slots[slot].is_some() {
+                self.run_slot(slot, |interp, input, events| {
+                    interp.step_events(input, events)
+                })?;
+            }
+        }
+
+        // 予定表のスロットが空なら何もしない。
+        let scheduled = self.damage_schedule.hits(self.frame).to_vec();
+        for slot in scheduled {
+            if self.enemy(slot).is_some() {
+                self.damage(slot)?;
+            }
+        }
+
+        if let Some(config) = self.collision {
+            self.collide(&config)?;
+        }
+
+        if let Some(rank) = &mut self.rank {
+            if !self.hero_hits.is_empty() {
+                rank.on_hero_hit();
+            }
+            rank.on_frame();
+        }
+
+        Ok(())
+    }
+
+    /// 自機弾を動かし、当たり判定を行う。
+    fn collide(&mut self, config: &CollisionConfig) -> WorldResult<()> {
+        let hero = (self.hero_x, self.hero_y);
+
+        // 自機弾は上端から出たら消える。
+        let speed = config.shot_speed;
+        self.shots.retain(|&(_, y)| y >= speed);
+        for (_, y) in &mut self.shots {
+            *y -= speed;
+        }
+        if config.shot_interval > 0
+            && self.frame % usize::from(config.shot_interval) == 0
+            && self.shots.len() < config.max_shots
+        {
+            self.shots.push(hero);
+        }
+
+        // 自機弾と敵。1 発の自機弾は 1 体の敵にのみ当たる。
+        let mut i = 0;
+        while i < self.shots.len() {
+            let shot = self.shots[i];
+            let target = self
+                .enemies()
+                .find(|(_, interp)| {
+                    config
+                        .shot
+                        .overlaps(shot, config.enemy, (interp.x(), interp.y()))
+                })
+                .map(|(slot, _)| slot);
+            match target {
+                Some(slot) => {
+                    self.shots.remove(i);
+                    self.damage(slot)?;
+                }
+                None => i += 1,
+            }
+        }
+
+        // 自機と敵。
+        for (slot, interp) in self.slots.iter().enumerate() {
+            if let Some(interp) = interp {
+                if config
+                    .hero
+                    .overlaps(hero, config.enemy, (interp.x(), interp.y()))
+                {
+                    self.hero_hits.push(HeroHit::Enemy { slot });
+                }
+            }
+        }
+
+        // 自機と敵弾。当たった敵弾は消える。
+        let hero_hits = &mut self.hero_hits;
+        self.bullets.retain(|bullet| {
+            let hit = config
+                .hero
+                .overlaps(hero, config.bullet, (bullet.x, bullet.y));
+            if hit {
+                hero_hits.push(HeroHit::Bullet {
+                    x: bullet.x,
+                    y: bullet.y,
+                });
+            }
+            !hit
+        });
+
+        Ok(())
+    }
+
+    /// 自機モデルの位置を自機位置として 1 フレーム進め、その後自機モデルを 1 フレーム進める。
+    pub fn step_hero<H: HeroModel>(&mut self, hero: &mut H) -> WorldResult<()> {
+        let (x, y) = hero.position();
+        self.set_hero(x, y);
+        self.step()?;
+        hero.step(self.bullets.bullets());
+
+        Ok(())
+    }
+
+    /// slot の敵にダメージを与える。イベントは events() に追加される。
+    pub fn damage(&mut self, slot: usize) -> WorldResult<()> {
+        if self.enemy(slot).is_none() {
+            return Err(WorldError::EmptySlot { slot });
+        }
+
+        self.run_slot(slot, |interp, input, events| {
+            interp.damage_events(input, events)
+        })
+    }
+
+    /// 空いている最小のスロットに敵を置き、そのスロット番号を返す。
+    /// 空きがなければ None を返す (敵は捨てられる)。
+    pub fn spawn(&mut self, interp: Interpreter) -> Option<usize> {
+        let slot = self.slots.iter().position(Option::is_none)?;
+        self.slots[slot] = Some(interp);
+        Some(slot)
+    }
+
+    /// slot の敵を取り除いて返す。
+    pub fn despawn(&mut self, slot: usize) -> Option<Interpreter> {
+        self.slots.get_mut(slot)?.take()
+    }
+
+    /// slot の敵を実行し、イベントを処理する。敵が生存状態でなくなったらスロットを空ける。
+    fn run_slot<F>(&mut self, slot: usize, f: F) -> WorldResult<()>
+    where
+        F: FnOnce(
+            &mut Interpreter,
+            &mut GameParts<FixedEnvironment, &mut R, (), ()>,
+            &mut Vec<Event>,
+        ) -> InterpretResult<()>,
+    {
+        let interp = self.slots[slot].as_mut().expect("slot must be occupied");
+
+        let mut input = GameParts {
+            env: FixedEnvironment {
+                stage: self.stage,
+                second_round: self.second_round,
+                hero_x: self.hero_x,
+                hero_y: self.hero_y,
+                rank: self.rank.as_ref().map(RankModel::rank),
+            },
+            rng: &mut self.rng,
+            shooter: (),
+            audio: (),
+        };
+        let mut events = vec![];
+        let res = f(interp, &mut input, &mut events);
+
+        for event in events {
+            self.bullets.shoot_event(&event);
+            if let (Event::Destroyed, Some(rank)) = (event, &mut self.rank) {
+                rank.on_kill();
+            }
+            self.events.push(WorldEvent { slot, event });
+        }
+        res.map_err(|source| WorldError::Interpret { slot, source })?;
+
+        if !matches!(interp.state(), EnemyState::Alive) {
+            self.slots[slot] = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R> World<R> {
+    /// 初期状態からのフレーム数。
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn stage(&self) -> u8 {
+        self.stage
+    }
+
+    pub fn is_second_round(&self) -> bool {
+        self.second_round
+    }
+
+    pub fn hero(&self) -> (u8, u8) {
+        (self.hero_x, self.hero_y)
+    }
+
+    pub fn set_hero(&mut self, x: u8, y: u8) {
+        self.hero_x = x;
+        self.hero_y = y;
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn enemy(&self, slot: usize) -> Option<&Interpreter> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn enemy_mut(&mut self, slot: usize) -> Option<&mut Interpreter> {
+        self.slots.get_mut(slot)?.as_mut()
+    }
+
+    /// 存在する敵を (スロット番号, 敵) の形でスロット番号順に返す。
+    pub fn enemies(&self) -> impl Iterator<Item = (usize, &Interpreter)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, interp)| interp.as_ref().map(|interp| (slot, interp)))
+    }
+
+    /// 敵が 1 体も存在しないかどうか。
+    pub fn is_clear(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    pub fn bullets(&self) -> &Bullets {
+        &self.bullets
+    }
+
+    pub fn bullets_mut(&mut self) -> &mut Bullets {
+        &mut self.bullets
+    }
+
+    pub fn rng(&self) -> &R {
+        &self.rng
+    }
+
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    pub fn collision(&self) -> Option<&CollisionConfig> {
+        self.collision.as_ref()
+    }
+
+    /// ランクのモデル。None ならランクは変化しない。
+    pub fn rank(&self) -> Option<&RankModel> {
+        self.rank.as_ref()
+    }
+
+    pub fn rank_mut(&mut self) -> Option<&mut RankModel> {
+        self.rank.as_mut()
+    }
+
+    pub fn damage_schedule(&self) -> &DamageSchedule {
+        &self.damage_schedule
+    }
+
+    pub fn damage_schedule_mut(&mut self) -> &mut DamageSchedule {
+        &mut self.damage_schedule
+    }
+
+    /// 自機弾の位置。
+    pub fn shots(&self) -> &[(u8, u8)] {
+        &self.shots
+    }
+
+    /// 直近の step() で自機が敵・敵弾に接触したかどうか。
+    pub fn hero_hits(&self) -> &[HeroHit] {
+        &self.hero_hits
+    }
+
+    /// 直近の step() 以降に発生したイベント。
+    pub fn events(&self) -> &[WorldEvent] {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Direction;
+    use crate::testutil::*;
+
+    /// 動かない敵。
+    const IDLE: &str = "L:\nset_sleep_timer 15\njump L\n";
+
+    fn new_world(
+        collision: Option<CollisionConfig>,
+        damage_schedule: DamageSchedule,
+    ) -> World<SeededRng> {
+        WorldInit {
+            stage: 1,
+            second_round: false,
+
+            hero_x: 100,
+            hero_y: 200,
+
+            max_enemies: 4,
+            bullet: BulletConfig::default(),
+
+            collision,
+            damage_schedule,
+
+            rank: None,
+
+            rng: SeededRng::default(),
+        }
+        .init()
+    }
+
+    fn enemy(x: u8, y: u8) -> Interpreter {
+        InterpreterInit {
+            x,
+            y,
+            ..interpreter_init(IDLE)
+        }
+        .init()
+        .unwrap()
+    }
+
+    #[test]
+    fn hero_shot_destroys_enemy() {
+        let mut world = new_world(
+            Some(CollisionConfig {
+                shot_interval: 1,
+                ..CollisionConfig::default()
+            }),
+            DamageSchedule::new(),
+        );
+        assert_eq!(world.spawn(enemy(100, 150)), Some(0));
+
+        for _ in 0..10 {
+            world.step().unwrap();
+            if world.is_clear() {
+                break;
+            }
+        }
+        assert!(world.is_clear());
+        assert!(world.events().contains(&WorldEvent {
+            slot: 0,
+            event: Event::Destroyed,
+        }));
+        assert!(world.shots().iter().all(|&(_, y)| y > 150));
+    }
+
+    #[test]
+    fn hero_contacts_are_reported() {
+        let config = CollisionConfig {
+            shot_interval: 0,
+            ..CollisionConfig::default()
+        };
+
+        let mut world = new_world(Some(config), DamageSchedule::new());
+        world.spawn(enemy(100, 190));
+        world.step().unwrap();
+        assert_eq!(world.hero_hits(), [HeroHit::Enemy { slot: 0 }]);
+        assert!(world.shots().is_empty());
+
+        let mut world = new_world(Some(config), DamageSchedule::new());
+        world
+            .bullets_mut()
+            .shoot_direction(100, 198, Direction::new(0x08));
+        world.step().unwrap();
+        assert!(matches!(world.hero_hits(), [HeroHit::Bullet { .. }]));
+        assert!(world.bullets().bullets().is_empty());
+    }
+
+    #[test]
+    fn damage_schedule_targets_slot() {
+        let mut world = new_world(None, "2:1".parse().unwrap());
+        world.spawn(enemy(50, 50));
+        world.spawn(enemy(150, 50));
+
+        world.step().unwrap();
+        assert!(world.enemy(1).is_some());
+        world.step().unwrap();
+        assert!(world.enemy(0).is_some());
+        assert!(world.enemy(1).is_none());
+        assert_eq!(
+            world.events(),
+            [
+                WorldEvent {
+                    slot: 1,
+                    event: Event::DestructionSound,
+                },
+                WorldEvent {
+                    slot: 1,
+                    event: Event::Destroyed,
+                },
+            ]
+        );
+    }
+}